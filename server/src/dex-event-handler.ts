@@ -13,20 +13,26 @@ interface DexEvent<T> {
   };
 }
 
-interface PoolCreatedEvent {
+// Every pool-scoped event carries the pool key, its id and a per-pool sequence number
+interface PoolScopedEvent {
   token0: string;
   token1: string;
   fee: number;
+  pool_id: string;
+  sequence: number;
+}
+
+interface PoolCreatedEvent extends PoolScopedEvent {
   tick_spacing: number;
   pool: string;
 }
 
-interface InitializeEvent {
+interface InitializeEvent extends PoolScopedEvent {
   sqrt_price_x96: string;
   tick: number;
 }
 
-interface MintEvent {
+interface MintEvent extends PoolScopedEvent {
   sender: string;
   owner: string;
   tick_lower: number;
@@ -36,7 +42,7 @@ interface MintEvent {
   amount1: string;
 }
 
-interface BurnEvent {
+interface BurnEvent extends PoolScopedEvent {
   owner: string;
   tick_lower: number;
   tick_upper: number;
@@ -45,7 +51,7 @@ interface BurnEvent {
   amount1: string;
 }
 
-interface CollectEvent {
+interface CollectEvent extends PoolScopedEvent {
   owner: string;
   recipient: string;
   tick_lower: number;
@@ -54,6 +60,19 @@ interface CollectEvent {
   amount1: string;
 }
 
+// Signed 128-bit amount, serialized as (abs, is_positive)
+type SignedAmount = [string, boolean];
+
+interface SwapEvent extends PoolScopedEvent {
+  sender: string;
+  recipient: string;
+  amount0: SignedAmount; // positive: paid into the pool
+  amount1: SignedAmount;
+  sqrt_price_x96: string;
+  liquidity: string;
+  tick: number;
+}
+
 async function fetchHistoricalEvents() {
   console.log('Fetching historical DEX events...');
   
@@ -92,11 +111,33 @@ async function fetchHistoricalEvents() {
   }
 }
 
+function formatSigned([abs, isPositive]: SignedAmount): string {
+  return isPositive || abs === '0' ? abs : `-${abs}`;
+}
+
 async function processEvent(event: any) {
   try {
     // Handle different event types based on event_type_name or data structure
-    if (event.event_type_name === 'PoolCreated' || (event.data?.token0 && event.data?.token1)) {
+    if (event.event_type_name === 'Swap' || (event.data?.recipient !== undefined && event.data?.liquidity !== undefined)) {
+      console.log('🔄 Swap:', {
+        pool_id: event.data.pool_id,
+        sequence: event.data.sequence,
+        token0: event.data.token0,
+        token1: event.data.token1,
+        fee: event.data.fee,
+        sender: event.data.sender,
+        recipient: event.data.recipient,
+        amount0: formatSigned(event.data.amount0),
+        amount1: formatSigned(event.data.amount1),
+        sqrt_price_x96: event.data.sqrt_price_x96,
+        liquidity: event.data.liquidity,
+        tick: event.data.tick,
+        deploy_hash: event.deploy_hash
+      });
+
+    } else if (event.event_type_name === 'PoolCreated' || event.data?.tick_spacing !== undefined) {
       console.log('📊 Pool Created:', {
+        pool_id: event.data.pool_id,
         token0: event.data.token0,
         token1: event.data.token1,
         fee: event.data.fee,
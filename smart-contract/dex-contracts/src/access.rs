@@ -0,0 +1,133 @@
+//! Role-based access control for the admin entry points
+//!
+//! Each admin duty is a separate role, so the keys that pause the DEX or collect protocol fees
+//! need not be able to do anything else. The owner grants and revokes roles, and hands ownership
+//! over in two steps: `transfer_ownership` nominates the new owner, who takes over with
+//! `accept_ownership` along with the roles the old owner still holds.
+
+use odra::prelude::*;
+use odra_modules::access::{
+    events::{OwnershipTransferStarted, OwnershipTransferred, RoleGranted, RoleRevoked},
+    AccessControl, Ownable2Step, Role,
+};
+use crate::errors::Error;
+
+/// Enables new fee tiers
+pub const FEE_TIER_ADMIN_ROLE: Role = role_id(b"FEE_TIER_ADMIN");
+/// Sets the protocol's share of swap fees and collects it
+pub const PROTOCOL_FEE_COLLECTOR_ROLE: Role = role_id(b"PROTOCOL_FEE_COLLECTOR");
+/// Pauses and unpauses swaps, mints and flash loans
+pub const PAUSER_ROLE: Role = role_id(b"PAUSER");
+/// Configures and removes price feeds
+pub const ORACLE_ADMIN_ROLE: Role = role_id(b"ORACLE_ADMIN");
+
+/// Every role, all granted to the deployer
+pub const ALL_ROLES: [Role; 4] = [FEE_TIER_ADMIN_ROLE, PROTOCOL_FEE_COLLECTOR_ROLE, PAUSER_ROLE, ORACLE_ADMIN_ROLE];
+
+/// Role identifier: the role name, zero-padded to 32 bytes
+const fn role_id(name: &[u8]) -> Role {
+    let mut role = [0u8; 32];
+    let mut i = 0;
+    while i < name.len() {
+        role[i] = name[i];
+        i += 1;
+    }
+    role
+}
+
+/// Owner plus role assignments, embedded by each contract with admin entry points
+#[odra::module(
+    events = [OwnershipTransferStarted, OwnershipTransferred, RoleGranted, RoleRevoked],
+    errors = Error
+)]
+pub struct Roles {
+    ownable: SubModule<Ownable2Step>,
+    access_control: SubModule<AccessControl>,
+}
+
+#[odra::module]
+impl Roles {
+    /// Whether `address` holds `role`
+    pub fn has_role(&self, role: Role, address: Address) -> bool {
+        self.access_control.has_role(&role, &address)
+    }
+
+    /// Account that grants and revokes roles
+    pub fn get_owner(&self) -> Address {
+        self.ownable.get_owner()
+    }
+
+    /// Account nominated by `transfer_ownership` that has not accepted yet, if any
+    pub fn get_pending_owner(&self) -> Option<Address> {
+        self.ownable.get_pending_owner()
+    }
+
+    /// Grant `role` to `address` (owner only)
+    pub fn grant_role(&mut self, role: Role, address: Address) {
+        self.assert_owner();
+        self.access_control.unchecked_grant_role(&role, &address);
+    }
+
+    /// Revoke `role` from `address` (owner only)
+    pub fn revoke_role(&mut self, role: Role, address: Address) {
+        self.assert_owner();
+        self.access_control.unchecked_revoke_role(&role, &address);
+    }
+
+    /// Give up `role` held by the caller, e.g. when its key may be compromised
+    pub fn renounce_role(&mut self, role: Role) {
+        let caller = self.env().caller();
+        self.access_control.unchecked_revoke_role(&role, &caller);
+    }
+
+    /// Nominate `new_owner`, replacing any earlier nomination (owner only)
+    ///
+    /// The current owner keeps control and its roles until `new_owner` calls `accept_ownership`.
+    pub fn transfer_ownership(&mut self, new_owner: Address) {
+        self.assert_owner();
+        self.ownable.transfer_ownership(&new_owner);
+    }
+
+    /// Take over ownership, and every role the old owner holds (pending owner only)
+    ///
+    /// Roles granted to other accounts are left as they are.
+    pub fn accept_ownership(&mut self) {
+        let new_owner = self.env().caller();
+        if self.get_pending_owner() != Some(new_owner) {
+            self.env().revert(Error::Unauthorized);
+        }
+        let old_owner = self.get_owner();
+        self.ownable.accept_ownership();
+
+        for role in ALL_ROLES {
+            if self.has_role(role, old_owner) {
+                self.access_control.unchecked_revoke_role(&role, &old_owner);
+                self.access_control.unchecked_grant_role(&role, &new_owner);
+            }
+        }
+    }
+}
+
+impl Roles {
+    /// Make `owner` the owner and grant it every role
+    pub fn init(&mut self, owner: Address) {
+        self.ownable.init(owner);
+        for role in ALL_ROLES {
+            self.access_control.unchecked_grant_role(&role, &owner);
+        }
+    }
+
+    /// Revert unless the caller is the owner
+    pub fn assert_owner(&self) {
+        if self.env().caller() != self.get_owner() {
+            self.env().revert(Error::Unauthorized);
+        }
+    }
+
+    /// Revert unless the caller holds `role`
+    pub fn assert_role(&self, role: Role) {
+        if !self.has_role(role, self.env().caller()) {
+            self.env().revert(Error::Unauthorized);
+        }
+    }
+}
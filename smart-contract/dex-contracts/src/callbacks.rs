@@ -0,0 +1,47 @@
+//! Callback interfaces that contracts interacting with the UnifiedDex must implement
+//!
+//! "Token0" and "token1" below are the first and second tokens as passed to the
+//! originating call, which may differ from the pool's sorted order.
+
+use odra::{casper_types::{bytesrepr::Bytes, U256}, prelude::*};
+use crate::types::tick::I128;
+
+/// Implemented by contracts that borrow through `UnifiedDex::flash`
+#[odra::external_contract]
+pub trait UnifiedDexFlashCallback {
+    /// Called on the borrower after the loaned amounts have been transferred.
+    /// The borrower must return `amount0 + fee0` and `amount1 + fee1` to the DEX
+    /// before this call returns.
+    ///
+    /// # Arguments
+    /// * `fee0` - Fee owed in token0 on top of the borrowed amount
+    /// * `fee1` - Fee owed in token1 on top of the borrowed amount
+    /// * `data` - Opaque data passed through from the `flash` call
+    fn flash_callback(&mut self, fee0: U256, fee1: U256, data: Bytes);
+}
+
+/// Implemented by contracts that swap through `UnifiedDex::swap_with_callback`
+#[odra::external_contract]
+pub trait UnifiedDexSwapCallback {
+    /// Called on the swapper after the output has been sent to the recipient.
+    /// The swapper must transfer the positive delta to the DEX before this call returns.
+    ///
+    /// # Arguments
+    /// * `amount0_delta` - Token0 owed to the pool (positive) or sent by it (negative)
+    /// * `amount1_delta` - Token1 owed to the pool (positive) or sent by it (negative)
+    /// * `data` - Opaque data passed through from the `swap_with_callback` call
+    fn swap_callback(&mut self, amount0_delta: I128, amount1_delta: I128, data: Bytes);
+}
+
+/// Implemented by contracts that add liquidity through `UnifiedDex::mint_with_callback`
+#[odra::external_contract]
+pub trait UnifiedDexMintCallback {
+    /// Called on the minter once the position has been updated.
+    /// The minter must transfer the owed amounts to the DEX before this call returns.
+    ///
+    /// # Arguments
+    /// * `amount0_owed` - Token0 owed to the pool for the minted liquidity
+    /// * `amount1_owed` - Token1 owed to the pool for the minted liquidity
+    /// * `data` - Opaque data passed through from the `mint_with_callback` call
+    fn mint_callback(&mut self, amount0_owed: U256, amount1_owed: U256, data: Bytes);
+}
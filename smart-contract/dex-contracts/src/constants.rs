@@ -37,3 +37,12 @@ pub const TICK_SPACING_LOW: i32 = 10;
 pub const TICK_SPACING_MEDIUM: i32 = 60;
 /// Tick spacing for 1.00% fee tier
 pub const TICK_SPACING_HIGH: i32 = 200;
+
+/// Pause flag: `swap` and `swap_with_callback`
+pub const PAUSE_SWAP: u8 = 1;
+/// Pause flag: `mint` and `mint_with_callback`
+pub const PAUSE_MINT: u8 = 2;
+/// Pause flag: `flash`
+pub const PAUSE_FLASH: u8 = 4;
+/// Every pausable action. `burn` and `collect` are never paused, so LPs can always exit
+pub const PAUSE_ALL: u8 = PAUSE_SWAP | PAUSE_MINT | PAUSE_FLASH;
@@ -0,0 +1,121 @@
+//! Errors the DEX contracts revert with
+//!
+//! Codes are grouped by area and exported in each contract's schema, so clients can
+//! decode a failed deploy's user error code back into a variant.
+
+use odra::prelude::*;
+
+/// Contract errors
+#[odra::odra_error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // Pools
+    /// The two tokens of a pair are the same
+    IdenticalTokens = 100,
+    /// The fee tier has not been enabled
+    FeeNotEnabled = 101,
+    /// A pool already exists for the token pair and fee tier
+    PoolAlreadyExists = 102,
+    /// No pool exists for the token pair and fee tier
+    PoolDoesNotExist = 103,
+    /// The pool already has a starting price
+    PoolAlreadyInitialized = 104,
+    /// The pool has no starting price yet
+    PoolNotInitialized = 105,
+    /// The pool is mid-update and cannot be re-entered
+    PoolLocked = 106,
+    /// The pool is already initialized at a different price than requested
+    PoolPriceMismatch = 107,
+    /// The fee is not below 100%
+    InvalidFee = 108,
+    /// The tick spacing is not between 1 and 16383
+    InvalidTickSpacing = 109,
+    /// The fee tier is already enabled
+    FeeAlreadyEnabled = 110,
+    /// The protocol fee denominator is neither 0 nor between 4 and 10
+    InvalidFeeProtocol = 111,
+    /// Only the pool creator or the owner may initialize the pool
+    NotPoolCreator = 112,
+    /// The pool holds fewer tokens than the operation pays out
+    InsufficientReserves = 113,
+
+    // Ticks and positions
+    /// The lower tick is not below the upper tick
+    InvalidTickRange = 200,
+    /// The tick is outside `MIN_TICK..=MAX_TICK`
+    TickOutOfBounds = 201,
+    /// The tick is not a multiple of the pool's tick spacing
+    TicksNotAligned = 202,
+    /// The tick is not referenced by any position
+    TickNotInitialized = 203,
+    /// The liquidity referencing a tick would exceed the per-tick maximum
+    LiquidityExceedsMax = 204,
+    /// The amount is zero
+    ZeroAmount = 205,
+    /// The position or pool has no liquidity
+    ZeroLiquidity = 206,
+    /// The liquidity does not fit in 128 bits
+    LiquidityOverflow = 207,
+    /// More liquidity is removed than is available
+    LiquidityUnderflow = 208,
+    /// The fees or tokens owed do not fit in 128 bits
+    FeesOverflow = 209,
+    /// No position exists for the token id
+    PositionNotFound = 210,
+
+    // Swaps and payments
+    /// The amounts received or paid are outside the caller's limits
+    SlippageExceeded = 300,
+    /// The swap price limit is not below the current price, or above `MAX_SQRT_RATIO`
+    PriceLimitTooHigh = 301,
+    /// The swap price limit is not above the current price, or below `MIN_SQRT_RATIO`
+    PriceLimitTooLow = 302,
+    /// Fewer tokens were paid to the DEX than owed
+    InsufficientPayment = 303,
+    /// A flash loan was not repaid with its fee
+    FlashLoanNotRepaid = 304,
+    /// Callback and flash entry points may only be called by contracts
+    CallerNotContract = 305,
+    /// The transaction deadline has passed
+    Expired = 306,
+    /// The swap path has fewer than two tokens or does not match its fees
+    InvalidPath = 307,
+    /// The callback data could not be decoded
+    InvalidCallbackData = 308,
+
+    // Math
+    /// A division by zero
+    DivisionByZero = 400,
+    /// A result does not fit in 256 bits
+    MathOverflow = 401,
+    /// The next sqrt price overflows
+    PriceOverflow = 402,
+    /// The next sqrt price underflows
+    PriceUnderflow = 403,
+    /// The sqrt price is zero or outside `MIN_SQRT_RATIO..MAX_SQRT_RATIO`
+    InvalidSqrtPrice = 404,
+    /// A token amount is too large to pass on as a 128-bit integer
+    AmountOverflow = 405,
+
+    // Access
+    /// The caller may not perform this action
+    Unauthorized = 500,
+    /// The action is paused globally or for the pool
+    Paused = 501,
+    /// The pause flags contain bits other than `PAUSE_ALL`
+    InvalidPauseActions = 502,
+
+    // Oracle and price feeds
+    /// The requested time is older than the oldest stored observation
+    ObservationTooOld = 600,
+    /// The observation cardinality is above 65535
+    CardinalityTooLarge = 601,
+    /// The averaging period is zero
+    ZeroPeriod = 602,
+    /// No price feed is configured for the pair
+    FeedNotConfigured = 603,
+    /// The intermediate token of a chained feed is one of the pair's tokens
+    InvalidIntermediateToken = 604,
+    /// The base token has more than 38 decimals
+    UnsupportedDecimals = 605,
+}
@@ -0,0 +1,42 @@
+//! Guards checked on entry to the UnifiedDex pool operations
+//!
+//! Global and per-pool pause flags decide whether an entry point may run at all.
+
+use odra::prelude::*;
+use crate::errors::Error;
+
+/// Global and per-pool `PAUSE_*` flags, embedded by `UnifiedDex`
+#[odra::module(errors = Error)]
+pub struct EntryGuard {
+    paused_actions: Var<u8>,  // PAUSE_* flags applied to every pool
+    pool_paused_actions: Mapping<[u8; 32], u8>,  // pool_id -> PAUSE_* flags for that pool only
+}
+
+impl EntryGuard {
+    /// `PAUSE_*` flags paused in every pool
+    pub fn paused_actions(&self) -> u8 {
+        self.paused_actions.get_or_default()
+    }
+
+    /// Replace the flags paused in every pool
+    pub fn set_paused_actions(&mut self, actions: u8) {
+        self.paused_actions.set(actions);
+    }
+
+    /// `PAUSE_*` flags paused in one pool, on top of the global ones
+    pub fn pool_paused_actions(&self, pool_id: [u8; 32]) -> u8 {
+        self.pool_paused_actions.get_or_default(&pool_id)
+    }
+
+    /// Replace the flags paused in one pool
+    pub fn set_pool_paused_actions(&mut self, pool_id: [u8; 32], actions: u8) {
+        self.pool_paused_actions.set(&pool_id, actions);
+    }
+
+    /// Revert if `action` is paused globally or for the pool
+    pub fn assert_not_paused(&self, pool_id: [u8; 32], action: u8) {
+        if (self.paused_actions() | self.pool_paused_actions(pool_id)) & action != 0 {
+            self.env().revert(Error::Paused);
+        }
+    }
+}
@@ -6,6 +6,8 @@ extern crate alloc;
 pub mod unified_dex;           // Main DEX contract (replaces factory + pools)
 pub mod unified_position_manager; // Position manager for unified DEX
 pub mod router;                // Multi-hop swap router
+pub mod price_feed;            // TWAP price feed adapter
+pub mod callbacks;             // Callback interfaces for flash loans, swaps and mints
 
 // Test token (for testing/demo purposes)
 pub mod test_token;
@@ -19,3 +21,19 @@ pub mod storage;
 
 // Constants
 pub mod constants;
+
+// Contract errors
+pub mod errors;
+
+// Admin roles and ownership
+pub mod access;
+
+// Pause flags
+pub mod guard;
+
+// Per-pool token reserves
+pub mod reserves;
+
+// Deployments shared by the contract tests
+#[cfg(test)]
+mod test_fixtures;
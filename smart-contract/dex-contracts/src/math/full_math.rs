@@ -1,6 +1,7 @@
 use odra::{
     casper_types::{U256, U512, bytesrepr::{ToBytes, FromBytes}},
 };
+use crate::errors::Error;
 
 /// Contains 512-bit math functions
 /// Based on Uniswap V3's FullMath.sol
@@ -28,7 +29,7 @@ impl FullMath {
     }
 
     /// Calculates floor(a×b÷denominator) with full precision
-    /// Throws if result overflows a uint256 or denominator == 0
+    /// Fails if result overflows a uint256 or denominator == 0
     ///
     /// # Arguments
     /// * `a` - The multiplicand
@@ -37,8 +38,10 @@ impl FullMath {
     ///
     /// # Returns
     /// The 256-bit result
-    pub fn mul_div(a: U256, b: U256, denominator: U256) -> U256 {
-        assert!(!denominator.is_zero(), "Division by zero");
+    pub fn mul_div(a: U256, b: U256, denominator: U256) -> Result<U256, Error> {
+        if denominator.is_zero() {
+            return Err(Error::DivisionByZero);
+        }
 
         // Use U512 to handle the full 512-bit product
         let product = Self::u256_to_u512(a) * Self::u256_to_u512(b);
@@ -48,13 +51,15 @@ impl FullMath {
 
         // Ensure the result fits in U256
         let max_u256 = Self::u256_to_u512(U256::MAX);
-        assert!(result <= max_u256, "Result overflow");
+        if result > max_u256 {
+            return Err(Error::MathOverflow);
+        }
 
-        Self::u512_to_u256(result)
+        Ok(Self::u512_to_u256(result))
     }
 
     /// Calculates ceil(a×b÷denominator) with full precision
-    /// Throws if result overflows a uint256 or denominator == 0
+    /// Fails if result overflows a uint256 or denominator == 0
     ///
     /// # Arguments
     /// * `a` - The multiplicand
@@ -63,19 +68,20 @@ impl FullMath {
     ///
     /// # Returns
     /// The 256-bit result rounded up
-    pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> U256 {
-        let result = Self::mul_div(a, b, denominator);
+    pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Result<U256, Error> {
+        let result = Self::mul_div(a, b, denominator)?;
         let remainder = Self::mul_mod(a, b, denominator);
 
         if remainder.is_zero() {
-            result
+            Ok(result)
+        } else if result == U256::MAX {
+            Err(Error::MathOverflow)
         } else {
-            assert!(result < U256::MAX, "Result overflow on rounding up");
-            result + U256::one()
+            Ok(result + U256::one())
         }
     }
 
-    /// Calculates (a × b) % denominator
+    /// Calculates (a × b) % denominator, for a denominator already checked to be nonzero
     ///
     /// # Arguments
     /// * `a` - The multiplicand
@@ -85,7 +91,6 @@ impl FullMath {
     /// # Returns
     /// The remainder
     fn mul_mod(a: U256, b: U256, denominator: U256) -> U256 {
-        assert!(!denominator.is_zero(), "Division by zero in mul_mod");
         let product = Self::u256_to_u512(a) * Self::u256_to_u512(b);
         let remainder = product % Self::u256_to_u512(denominator);
         Self::u512_to_u256(remainder)
@@ -104,7 +109,7 @@ mod tests {
             U256::from(20u32),
             U256::from(5u32),
         );
-        assert_eq!(result, U256::from(40u32));
+        assert_eq!(result, Ok(U256::from(40u32)));
     }
 
     #[test]
@@ -115,7 +120,7 @@ mod tests {
             U256::from(7u32),
             U256::from(3u32),
         );
-        assert_eq!(result, U256::from(23u32));
+        assert_eq!(result, Ok(U256::from(23u32)));
     }
 
     #[test]
@@ -126,7 +131,7 @@ mod tests {
             U256::from(7u32),
             U256::from(3u32),
         );
-        assert_eq!(result, U256::from(24u32));
+        assert_eq!(result, Ok(U256::from(24u32)));
     }
 
     #[test]
@@ -137,16 +142,22 @@ mod tests {
             U256::from(6u32),
             U256::from(3u32),
         );
-        assert_eq!(result, U256::from(20u32));
+        assert_eq!(result, Ok(U256::from(20u32)));
     }
 
     #[test]
-    #[should_panic(expected = "Division by zero")]
     fn test_mul_div_zero_denominator() {
-        FullMath::mul_div(
+        let result = FullMath::mul_div(
             U256::from(10u32),
             U256::from(20u32),
             U256::zero(),
         );
+        assert_eq!(result, Err(Error::DivisionByZero));
+    }
+
+    #[test]
+    fn test_mul_div_overflow() {
+        assert_eq!(FullMath::mul_div(U256::MAX, U256::from(2u32), U256::one()), Err(Error::MathOverflow));
+        assert_eq!(FullMath::mul_div_rounding_up(U256::MAX, U256::from(3u32), U256::from(2u32)), Err(Error::MathOverflow));
     }
 }
\ No newline at end of file
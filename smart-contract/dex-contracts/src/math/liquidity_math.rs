@@ -1,13 +1,14 @@
 use odra::{
     casper_types::{U256, U128},
 };
+use crate::errors::Error;
 
 /// Math for managing liquidity positions
 /// Based on Uniswap V3's LiquidityMath.sol
 pub struct LiquidityMath;
 
 impl LiquidityMath {
-    /// Add a signed liquidity delta to liquidity and revert if it overflows or underflows
+    /// Add a signed liquidity delta to liquidity and fail if it overflows or underflows
     ///
     /// # Arguments
     /// * `liquidity` - The liquidity before change
@@ -15,19 +16,12 @@ impl LiquidityMath {
     ///
     /// # Returns
     /// The liquidity after adding the delta
-    pub fn add_delta(liquidity: U128, liquidity_delta: i128) -> U128 {
+    pub fn add_delta(liquidity: U128, liquidity_delta: i128) -> Result<U128, Error> {
+        let delta_abs = liquidity_delta.unsigned_abs().into();
         if liquidity_delta < 0 {
-            let delta_abs = liquidity_delta.unsigned_abs().into();
-            assert!(
-                liquidity >= delta_abs,
-                "Liquidity underflow"
-            );
-            liquidity - delta_abs
+            liquidity.checked_sub(delta_abs).ok_or(Error::LiquidityUnderflow)
         } else {
-            let delta_abs = liquidity_delta.unsigned_abs().into();
-            let result = liquidity.checked_add(delta_abs);
-            assert!(result.is_some(), "Liquidity overflow");
-            result.unwrap()
+            liquidity.checked_add(delta_abs).ok_or(Error::LiquidityOverflow)
         }
     }
 
@@ -49,7 +43,7 @@ impl LiquidityMath {
         sqrt_ratio_b_x96: U256,
         amount0: U256,
         amount1: U256,
-    ) -> U128 {
+    ) -> Result<U128, Error> {
         let (sqrt_ratio_a_x96, sqrt_ratio_b_x96) = if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
             (sqrt_ratio_b_x96, sqrt_ratio_a_x96)
         } else {
@@ -61,11 +55,11 @@ impl LiquidityMath {
             Self::get_liquidity_for_amount0(sqrt_ratio_a_x96, sqrt_ratio_b_x96, amount0)
         } else if sqrt_ratio_x96 < sqrt_ratio_b_x96 {
             // Current price is within the range, use both tokens
-            let liquidity0 = Self::get_liquidity_for_amount0(sqrt_ratio_x96, sqrt_ratio_b_x96, amount0);
-            let liquidity1 = Self::get_liquidity_for_amount1(sqrt_ratio_a_x96, sqrt_ratio_x96, amount1);
+            let liquidity0 = Self::get_liquidity_for_amount0(sqrt_ratio_x96, sqrt_ratio_b_x96, amount0)?;
+            let liquidity1 = Self::get_liquidity_for_amount1(sqrt_ratio_a_x96, sqrt_ratio_x96, amount1)?;
 
             // Return the minimum to ensure we don't exceed either token amount
-            liquidity0.min(liquidity1)
+            Ok(liquidity0.min(liquidity1))
         } else {
             // Current price is above the range, use only token1
             Self::get_liquidity_for_amount1(sqrt_ratio_a_x96, sqrt_ratio_b_x96, amount1)
@@ -85,7 +79,7 @@ impl LiquidityMath {
         sqrt_ratio_a_x96: U256,
         sqrt_ratio_b_x96: U256,
         amount0: U256,
-    ) -> U128 {
+    ) -> Result<U128, Error> {
         let (sqrt_ratio_a_x96, sqrt_ratio_b_x96) = if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
             (sqrt_ratio_b_x96, sqrt_ratio_a_x96)
         } else {
@@ -97,8 +91,10 @@ impl LiquidityMath {
 
         // Ensure it fits in U128
         let u128_max_as_u256 = (U256::one() << 128) - U256::one();
-        assert!(liquidity <= u128_max_as_u256, "Liquidity overflow");
-        U128::from(liquidity.as_u128())
+        if liquidity > u128_max_as_u256 {
+            return Err(Error::LiquidityOverflow);
+        }
+        Ok(U128::from(liquidity.as_u128()))
     }
 
     /// Computes the amount of liquidity received for a given amount of token1 and price range
@@ -114,7 +110,7 @@ impl LiquidityMath {
         sqrt_ratio_a_x96: U256,
         sqrt_ratio_b_x96: U256,
         amount1: U256,
-    ) -> U128 {
+    ) -> Result<U128, Error> {
         let (sqrt_ratio_a_x96, sqrt_ratio_b_x96) = if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
             (sqrt_ratio_b_x96, sqrt_ratio_a_x96)
         } else {
@@ -125,8 +121,10 @@ impl LiquidityMath {
 
         // Ensure it fits in U128
         let u128_max_as_u256 = (U256::one() << 128) - U256::one();
-        assert!(liquidity <= u128_max_as_u256, "Liquidity overflow");
-        U128::from(liquidity.as_u128())
+        if liquidity > u128_max_as_u256 {
+            return Err(Error::LiquidityOverflow);
+        }
+        Ok(U128::from(liquidity.as_u128()))
     }
 }
 
@@ -140,7 +138,7 @@ mod tests {
         let liquidity = 1000u128.into();
         let delta = 500i128;
         let result = LiquidityMath::add_delta(liquidity, delta);
-        assert_eq!(result, 1500u128.into());
+        assert_eq!(result, Ok(1500u128.into()));
     }
 
     #[test]
@@ -148,7 +146,7 @@ mod tests {
         let liquidity = 1000u128.into();
         let delta = -500i128;
         let result = LiquidityMath::add_delta(liquidity, delta);
-        assert_eq!(result, 500u128.into());
+        assert_eq!(result, Ok(500u128.into()));
     }
 
     #[test]
@@ -156,15 +154,15 @@ mod tests {
         let liquidity = 1000u128.into();
         let delta = 0i128;
         let result = LiquidityMath::add_delta(liquidity, delta);
-        assert_eq!(result, 1000u128.into());
+        assert_eq!(result, Ok(1000u128.into()));
     }
 
     #[test]
-    #[should_panic(expected = "Liquidity underflow")]
     fn test_add_delta_underflow() {
         let liquidity = 100u128.into();
         let delta = -500i128;
-        LiquidityMath::add_delta(liquidity, delta);
+        assert_eq!(LiquidityMath::add_delta(liquidity, delta), Err(Error::LiquidityUnderflow));
+        assert_eq!(LiquidityMath::add_delta(U128::MAX, 1), Err(Error::LiquidityOverflow));
     }
 
     #[test]
@@ -177,7 +175,7 @@ mod tests {
             sqrt_ratio_a,
             sqrt_ratio_b,
             amount0,
-        );
+        ).unwrap();
 
         assert!(liquidity > 0.into());
     }
@@ -192,7 +190,7 @@ mod tests {
             sqrt_ratio_a,
             sqrt_ratio_b,
             amount1,
-        );
+        ).unwrap();
 
         assert!(liquidity > 0.into());
     }
@@ -210,7 +208,7 @@ mod tests {
             upper_price,
             U256::from(1000000u64),
             U256::from(1000000u64),
-        );
+        ).unwrap();
 
         assert!(liquidity > 0.into());
     }
@@ -228,7 +226,7 @@ mod tests {
             upper_price,
             U256::from(1000000u64),
             U256::from(1000000u64),
-        );
+        ).unwrap();
 
         assert!(liquidity > 0.into());
     }
@@ -246,7 +244,7 @@ mod tests {
             upper_price,
             U256::from(1000000u64),
             U256::from(1000000u64),
-        );
+        ).unwrap();
 
         assert!(liquidity > 0.into());
     }
@@ -2,8 +2,12 @@ pub mod tick_math;
 pub mod sqrt_price_math;
 pub mod liquidity_math;
 pub mod full_math;
+pub mod swap_math;
+pub mod oracle_library;
 
 pub use tick_math::TickMath;
 pub use sqrt_price_math::SqrtPriceMath;
 pub use liquidity_math::LiquidityMath;
 pub use full_math::FullMath;
+pub use swap_math::SwapMath;
+pub use oracle_library::OracleLibrary;
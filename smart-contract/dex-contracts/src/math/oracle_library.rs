@@ -0,0 +1,167 @@
+use odra::{
+    casper_types::{U128, U256},
+    prelude::*,
+};
+use crate::errors::Error;
+use crate::math::full_math::FullMath;
+use crate::math::tick_math::TickMath;
+
+/// Provides functions to integrate with the pool oracle
+/// Based on Uniswap V3's OracleLibrary.sol
+pub struct OracleLibrary;
+
+impl OracleLibrary {
+    /// Calculates time-weighted means of tick and liquidity from two `observe` snapshots
+    ///
+    /// # Arguments
+    /// * `tick_cumulatives` - Tick cumulatives `period` seconds ago and now
+    /// * `seconds_per_liquidity_cumulative_x128s` - Seconds per liquidity cumulatives `period` seconds ago and now
+    /// * `period` - Number of seconds between the two snapshots
+    ///
+    /// # Returns
+    /// * `arithmetic_mean_tick` - The arithmetic mean tick over the period, rounded to negative infinity
+    /// * `harmonic_mean_liquidity` - The harmonic mean liquidity over the period (zero if none was in range)
+    pub fn consult(
+        tick_cumulatives: (i64, i64),
+        seconds_per_liquidity_cumulative_x128s: (U256, U256),
+        period: u32,
+    ) -> Result<(i32, U128), Error> {
+        if period == 0 {
+            return Err(Error::ZeroPeriod);
+        }
+
+        let tick_cumulatives_delta = tick_cumulatives.1 - tick_cumulatives.0;
+        let mut arithmetic_mean_tick = (tick_cumulatives_delta / period as i64) as i32;
+        // Always round to negative infinity
+        if tick_cumulatives_delta < 0 && tick_cumulatives_delta % period as i64 != 0 {
+            arithmetic_mean_tick -= 1;
+        }
+
+        let seconds_per_liquidity_delta = seconds_per_liquidity_cumulative_x128s.1
+            .overflowing_sub(seconds_per_liquidity_cumulative_x128s.0).0;
+        if seconds_per_liquidity_delta.is_zero() {
+            return Ok((arithmetic_mean_tick, U128::zero()));
+        }
+
+        // period / (delta / 2^128), computed as in Uniswap with a 160-bit numerator to limit overflow.
+        // Uniswap divides by `delta << 32` on a 160-bit delta; dividing the quotient by 2^32
+        // instead gives the same floor without dropping the high bits of a 256-bit delta.
+        let harmonic_mean_liquidity = FullMath::mul_div(
+            U256::from(period),
+            (U256::one() << 160) - U256::one(),
+            seconds_per_liquidity_delta,
+        )? >> 32;
+        let harmonic_mean_liquidity = if harmonic_mean_liquidity > U256::from(u128::MAX) {
+            U128::MAX
+        } else {
+            U128::from(harmonic_mean_liquidity.as_u128())
+        };
+
+        Ok((arithmetic_mean_tick, harmonic_mean_liquidity))
+    }
+
+    /// Given a tick and a token amount, calculates the amount of token received in exchange
+    ///
+    /// # Arguments
+    /// * `tick` - Tick value used to calculate the quote
+    /// * `base_amount` - Amount of token to be converted
+    /// * `base_token` - Address of the token being quoted
+    /// * `quote_token` - Address of the token used as denomination of the quote
+    ///
+    /// # Returns
+    /// Amount of `quote_token` received for `base_amount` of `base_token`
+    pub fn get_quote_at_tick(
+        tick: i32,
+        base_amount: U128,
+        base_token: Address,
+        quote_token: Address,
+    ) -> Result<U256, Error> {
+        let sqrt_ratio_x96 = TickMath::get_sqrt_ratio_at_tick(tick)?;
+        let base_amount = U256::from(base_amount.as_u128());
+
+        // Calculate quote_amount with better precision if it doesn't overflow when multiplied by itself
+        if sqrt_ratio_x96 <= U256::from(u128::MAX) {
+            let ratio_x192 = sqrt_ratio_x96 * sqrt_ratio_x96;
+            if base_token < quote_token {
+                FullMath::mul_div(ratio_x192, base_amount, U256::one() << 192)
+            } else {
+                FullMath::mul_div(U256::one() << 192, base_amount, ratio_x192)
+            }
+        } else {
+            let ratio_x128 = FullMath::mul_div(sqrt_ratio_x96, sqrt_ratio_x96, U256::one() << 64)?;
+            if base_token < quote_token {
+                FullMath::mul_div(ratio_x128, base_amount, U256::one() << 128)
+            } else {
+                FullMath::mul_div(U256::one() << 128, base_amount, ratio_x128)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use odra::casper_types::account::AccountHash;
+
+    fn tokens() -> (Address, Address) {
+        (
+            Address::from(AccountHash::new([1u8; 32])),
+            Address::from(AccountHash::new([2u8; 32])),
+        )
+    }
+
+    #[test]
+    fn test_consult_rounds_mean_tick_down() {
+        let spl = (U256::zero(), U256::from(10u32) << 128);
+
+        assert_eq!(OracleLibrary::consult((0, 1000), spl, 10).unwrap().0, 100);
+        assert_eq!(OracleLibrary::consult((0, -1000), spl, 10).unwrap().0, -100);
+        assert_eq!(OracleLibrary::consult((0, -1005), spl, 10).unwrap().0, -101);
+        assert_eq!(OracleLibrary::consult((0, 0), spl, 0), Err(Error::ZeroPeriod));
+    }
+
+    #[test]
+    fn test_consult_harmonic_mean_liquidity() {
+        // 10 seconds at a constant liquidity of 1000
+        let spl = (U256::zero(), (U256::from(10u32) << 128) / U256::from(1000u32));
+        let (_, liquidity) = OracleLibrary::consult((0, 0), spl, 10).unwrap();
+
+        // Uniswap's 160-bit numerator loses at most one unit
+        assert!(liquidity == U128::from(1000u32) || liquidity == U128::from(999u32));
+        assert_eq!(OracleLibrary::consult((0, 0), (U256::zero(), U256::zero()), 10).unwrap().1, U128::zero());
+    }
+
+    #[test]
+    fn test_consult_keeps_high_bits_of_large_deltas() {
+        // Shifting this delta left by 32 bits would leave only 1000 << 32 and report huge liquidity
+        let spl = (U256::zero(), (U256::one() << 224) + U256::from(1000u32));
+        assert_eq!(OracleLibrary::consult((0, 0), spl, 10).unwrap().1, U128::zero());
+
+        // And this one would wrap to a zero divisor
+        let spl = (U256::zero(), U256::one() << 230);
+        assert_eq!(OracleLibrary::consult((0, 0), spl, 10).unwrap().1, U128::zero());
+
+        // A liquidity of 1 loses its unit to the 160-bit numerator, exactly as in Uniswap
+        let spl = (U256::zero(), U256::from(10u32) << 128);
+        assert_eq!(OracleLibrary::consult((0, 0), spl, 10).unwrap().1, U128::zero());
+    }
+
+    #[test]
+    fn test_get_quote_at_tick() {
+        let (token0, token1) = tokens();
+        let amount = U128::from(1_000_000u128);
+
+        // Tick 0 is a price of 1 in both directions
+        assert_eq!(OracleLibrary::get_quote_at_tick(0, amount, token0, token1).unwrap(), U256::from(1_000_000u128));
+        assert_eq!(OracleLibrary::get_quote_at_tick(0, amount, token1, token0).unwrap(), U256::from(1_000_000u128));
+
+        // About 1.0001^6932 ≈ 2 token1 per token0, and the inverse
+        let quote = OracleLibrary::get_quote_at_tick(6932, amount, token0, token1).unwrap();
+        assert!(quote > U256::from(1_999_000u128) && quote < U256::from(2_001_000u128));
+        let quote = OracleLibrary::get_quote_at_tick(6932, amount, token1, token0).unwrap();
+        assert!(quote > U256::from(499_000u128) && quote < U256::from(501_000u128));
+
+        // Large ticks take the lower-precision path without overflowing
+        assert!(!OracleLibrary::get_quote_at_tick(500_000, amount, token0, token1).unwrap().is_zero());
+    }
+}
@@ -3,6 +3,7 @@ use odra::{
 };
 use crate::math::full_math::FullMath;
 use crate::constants::Q96;
+use crate::errors::Error;
 
 /// Contains the math that uses square root of price as a Q64.96 and liquidity to compute deltas
 /// Based on Uniswap V3's SqrtPriceMath.sol
@@ -31,30 +32,38 @@ impl SqrtPriceMath {
         liquidity: U128,
         amount: U256,
         add: bool,
-    ) -> U256 {
+    ) -> Result<U256, Error> {
         if amount.is_zero() {
-            return sqrt_price_x96;
+            return Ok(sqrt_price_x96);
         }
 
         let numerator1 = Self::u128_to_u256(liquidity) << 96;
 
+        let (product, product_overflow) = amount.overflowing_mul(sqrt_price_x96);
+
         if add {
             // If adding liquidity, round down to avoid giving too much
-            let product = amount * sqrt_price_x96;
-            let denominator = numerator1 + product;
+            if !product_overflow {
+                let (denominator, denominator_overflow) = numerator1.overflowing_add(product);
+                if !denominator_overflow {
+                    return FullMath::mul_div_rounding_up(numerator1, sqrt_price_x96, denominator);
+                }
+            }
 
-            if denominator >= numerator1 {
-                // No overflow
-                return FullMath::mul_div_rounding_up(numerator1, sqrt_price_x96, denominator);
+            // On overflow, use the equivalent liquidity / (liquidity / price + amount)
+            FullMath::mul_div_rounding_up(
+                numerator1,
+                U256::one(),
+                (numerator1 / sqrt_price_x96).checked_add(amount).ok_or(Error::PriceOverflow)?,
+            )
+        } else {
+            // If removing, the amount must be less than the virtual reserves of token0
+            if product_overflow || numerator1 <= product {
+                return Err(Error::PriceOverflow);
             }
+            let denominator = numerator1 - product;
+            FullMath::mul_div_rounding_up(numerator1, sqrt_price_x96, denominator)
         }
-
-        // If removing liquidity or overflow, use different formula
-        FullMath::mul_div_rounding_up(
-            numerator1,
-            U256::one(),
-            (numerator1 / sqrt_price_x96) + amount,
-        )
     }
 
     /// Gets the next sqrt price given a delta of token1
@@ -72,31 +81,33 @@ impl SqrtPriceMath {
         liquidity: U128,
         amount: U256,
         add: bool,
-    ) -> U256 {
+    ) -> Result<U256, Error> {
         if add {
             // If adding liquidity, compute new price directly
             let quotient = if amount <= Self::u128_to_u256(U128::MAX) {
                 (amount << 96) / Self::u128_to_u256(liquidity)
             } else {
-                FullMath::mul_div(amount, U256::from(Q96), Self::u128_to_u256(liquidity))
+                FullMath::mul_div(amount, U256::from(Q96), Self::u128_to_u256(liquidity))?
             };
 
-            sqrt_price_x96 + quotient
+            sqrt_price_x96.checked_add(quotient).ok_or(Error::PriceOverflow)
         } else {
             // If removing liquidity
             let quotient = if amount <= Self::u128_to_u256(U128::MAX) {
-                FullMath::mul_div_rounding_up(amount, U256::from(Q96), Self::u128_to_u256(liquidity))
+                FullMath::mul_div_rounding_up(amount, U256::from(Q96), Self::u128_to_u256(liquidity))?
             } else {
-                FullMath::mul_div_rounding_up(amount, U256::from(Q96), Self::u128_to_u256(liquidity))
+                FullMath::mul_div_rounding_up(amount, U256::from(Q96), Self::u128_to_u256(liquidity))?
             };
 
-            assert!(sqrt_price_x96 > quotient, "Price underflow");
-            sqrt_price_x96 - quotient
+            if sqrt_price_x96 <= quotient {
+                return Err(Error::PriceUnderflow);
+            }
+            Ok(sqrt_price_x96 - quotient)
         }
     }
 
     /// Gets the next sqrt price given an input amount of token0 or token1
-    /// Throws if price or liquidity are 0, or if the next price is out of bounds
+    /// Fails if price or liquidity are 0, or if the next price is out of bounds
     ///
     /// # Arguments
     /// * `sqrt_price_x96` - The starting price (Q64.96)
@@ -111,9 +122,13 @@ impl SqrtPriceMath {
         liquidity: U128,
         amount_in: U256,
         zero_for_one: bool,
-    ) -> U256 {
-        assert!(!sqrt_price_x96.is_zero(), "sqrt_price_x96 is zero");
-        assert!(!liquidity.is_zero(), "Liquidity is zero");
+    ) -> Result<U256, Error> {
+        if sqrt_price_x96.is_zero() {
+            return Err(Error::InvalidSqrtPrice);
+        }
+        if liquidity.is_zero() {
+            return Err(Error::ZeroLiquidity);
+        }
 
         if zero_for_one {
             Self::get_next_sqrt_price_from_amount0_rounding_up(
@@ -133,7 +148,7 @@ impl SqrtPriceMath {
     }
 
     /// Gets the next sqrt price given an output amount of token0 or token1
-    /// Throws if price or liquidity are 0, or if the next price is out of bounds
+    /// Fails if price or liquidity are 0, or if the next price is out of bounds
     ///
     /// # Arguments
     /// * `sqrt_price_x96` - The starting price (Q64.96)
@@ -148,9 +163,13 @@ impl SqrtPriceMath {
         liquidity: U128,
         amount_out: U256,
         zero_for_one: bool,
-    ) -> U256 {
-        assert!(!sqrt_price_x96.is_zero(), "sqrt_price_x96 is zero");
-        assert!(!liquidity.is_zero(), "Liquidity is zero");
+    ) -> Result<U256, Error> {
+        if sqrt_price_x96.is_zero() {
+            return Err(Error::InvalidSqrtPrice);
+        }
+        if liquidity.is_zero() {
+            return Err(Error::ZeroLiquidity);
+        }
 
         if zero_for_one {
             Self::get_next_sqrt_price_from_amount1_rounding_down(
@@ -184,7 +203,7 @@ impl SqrtPriceMath {
         sqrt_ratio_bx96: U256,
         liquidity: U128,
         round_up: bool,
-    ) -> U256 {
+    ) -> Result<U256, Error> {
         let (sqrt_ratio_ax96, sqrt_ratio_bx96) = if sqrt_ratio_ax96 > sqrt_ratio_bx96 {
             (sqrt_ratio_bx96, sqrt_ratio_ax96)
         } else {
@@ -194,17 +213,19 @@ impl SqrtPriceMath {
         let numerator1 = Self::u128_to_u256(liquidity) << 96;
         let numerator2 = sqrt_ratio_bx96 - sqrt_ratio_ax96;
 
-        assert!(!sqrt_ratio_ax96.is_zero(), "sqrt_ratio_ax96 is zero");
+        if sqrt_ratio_ax96.is_zero() {
+            return Err(Error::InvalidSqrtPrice);
+        }
 
         if round_up {
             FullMath::mul_div_rounding_up(
-                FullMath::mul_div_rounding_up(numerator1, numerator2, sqrt_ratio_bx96),
+                FullMath::mul_div_rounding_up(numerator1, numerator2, sqrt_ratio_bx96)?,
                 U256::one(),
                 sqrt_ratio_ax96,
             )
         } else {
             FullMath::mul_div(
-                FullMath::mul_div(numerator1, numerator2, sqrt_ratio_bx96),
+                FullMath::mul_div(numerator1, numerator2, sqrt_ratio_bx96)?,
                 U256::one(),
                 sqrt_ratio_ax96,
             )
@@ -226,7 +247,7 @@ impl SqrtPriceMath {
         sqrt_ratio_bx96: U256,
         liquidity: U128,
         round_up: bool,
-    ) -> U256 {
+    ) -> Result<U256, Error> {
         let (sqrt_ratio_ax96, sqrt_ratio_bx96) = if sqrt_ratio_ax96 > sqrt_ratio_bx96 {
             (sqrt_ratio_bx96, sqrt_ratio_ax96)
         } else {
@@ -258,7 +279,7 @@ mod tests {
             sqrt_price_b,
             liquidity.into(),
             false,
-        );
+        ).unwrap();
 
         assert!(amount > U256::zero());
     }
@@ -274,7 +295,7 @@ mod tests {
             sqrt_price_b,
             liquidity.into(),
             false,
-        );
+        ).unwrap();
 
         assert!(amount > U256::zero());
     }
@@ -290,7 +311,7 @@ mod tests {
             liquidity.into(),
             amount_in,
             true,
-        );
+        ).unwrap();
 
         // Price should decrease when swapping token0 for token1
         assert!(new_price < sqrt_price);
@@ -307,31 +328,62 @@ mod tests {
             liquidity.into(),
             amount_in,
             false,
-        );
+        ).unwrap();
 
         // Price should increase when swapping token1 for token0
         assert!(new_price > sqrt_price);
     }
 
     #[test]
-    #[should_panic(expected = "sqrt_price_x96 is zero")]
+    fn test_get_next_sqrt_price_from_output_one_for_zero() {
+        let sqrt_price = U256::from(Q96);
+        let liquidity = 1000000u128;
+        let amount_out = U256::from(1000u32);
+
+        let new_price = SqrtPriceMath::get_next_sqrt_price_from_output(
+            sqrt_price,
+            liquidity.into(),
+            amount_out,
+            false,
+        ).unwrap();
+
+        // Removing token0 raises the price, and the output is covered at the new price
+        assert!(new_price > sqrt_price);
+        let covered = SqrtPriceMath::get_amount0_delta(sqrt_price, new_price, liquidity.into(), false).unwrap();
+        assert!(covered >= amount_out);
+    }
+
+    #[test]
     fn test_get_next_sqrt_price_zero_price() {
-        SqrtPriceMath::get_next_sqrt_price_from_input(
+        let result = SqrtPriceMath::get_next_sqrt_price_from_input(
             U256::zero(),
             1000000u128.into(),
             U256::from(1000u32),
             true,
         );
+        assert_eq!(result, Err(Error::InvalidSqrtPrice));
     }
 
     #[test]
-    #[should_panic(expected = "Liquidity is zero")]
     fn test_get_next_sqrt_price_zero_liquidity() {
-        SqrtPriceMath::get_next_sqrt_price_from_input(
+        let result = SqrtPriceMath::get_next_sqrt_price_from_input(
             U256::from(Q96),
             0u128.into(),
             U256::from(1000u32),
             true,
         );
+        assert_eq!(result, Err(Error::ZeroLiquidity));
+    }
+
+    #[test]
+    fn test_get_next_sqrt_price_output_exceeds_reserves() {
+        // Removing more token1 than the virtual reserves would push the price below zero
+        let result = SqrtPriceMath::get_next_sqrt_price_from_output(
+            U256::from(Q96),
+            1000u128.into(),
+            U256::from(1_000_000u32),
+            true,
+        );
+        assert_eq!(result, Err(Error::PriceUnderflow));
     }
 }
@@ -0,0 +1,195 @@
+use odra::casper_types::{U128, U256};
+use crate::math::full_math::FullMath;
+use crate::math::sqrt_price_math::SqrtPriceMath;
+use crate::errors::Error;
+
+/// Computes the result of a swap within ticks
+/// Based on Uniswap V3's SwapMath.sol
+pub struct SwapMath;
+
+impl SwapMath {
+    /// Computes the result of swapping some amount in, or amount out, given the parameters of the swap
+    /// The fee, plus the amount in, will never exceed the amount remaining if the swap's `amount_remaining` is positive
+    ///
+    /// # Arguments
+    /// * `sqrt_ratio_current_x96` - The current sqrt price of the pool
+    /// * `sqrt_ratio_target_x96` - The price that cannot be exceeded, from which the direction of the swap is inferred
+    /// * `liquidity` - The usable liquidity
+    /// * `amount_remaining` - How much input or output amount is remaining to be swapped in/out (positive = exact input)
+    /// * `fee_pips` - The fee taken from the input amount, expressed in hundredths of a bip
+    ///
+    /// # Returns
+    /// * `sqrt_ratio_next_x96` - The price after swapping the amount in/out, not to exceed the price target
+    /// * `amount_in` - The amount to be swapped in, of either token0 or token1, based on the direction of the swap
+    /// * `amount_out` - The amount to be received, of either token0 or token1, based on the direction of the swap
+    /// * `fee_amount` - The amount of input that will be taken as a fee
+    pub fn compute_swap_step(
+        sqrt_ratio_current_x96: U256,
+        sqrt_ratio_target_x96: U256,
+        liquidity: U128,
+        amount_remaining: i128,
+        fee_pips: u32,
+    ) -> Result<(U256, U256, U256, U256), Error> {
+        let zero_for_one = sqrt_ratio_current_x96 >= sqrt_ratio_target_x96;
+        let exact_in = amount_remaining >= 0;
+        let amount_remaining_abs = U256::from(amount_remaining.unsigned_abs());
+        let one_million = U256::from(1_000_000u32);
+
+        let mut amount_in = U256::zero();
+        let mut amount_out = U256::zero();
+
+        let sqrt_ratio_next_x96 = if exact_in {
+            let amount_remaining_less_fee = FullMath::mul_div(
+                amount_remaining_abs,
+                U256::from(1_000_000 - fee_pips),
+                one_million,
+            )?;
+            amount_in = if zero_for_one {
+                SqrtPriceMath::get_amount0_delta(sqrt_ratio_target_x96, sqrt_ratio_current_x96, liquidity, true)?
+            } else {
+                SqrtPriceMath::get_amount1_delta(sqrt_ratio_current_x96, sqrt_ratio_target_x96, liquidity, true)?
+            };
+            if amount_remaining_less_fee >= amount_in {
+                sqrt_ratio_target_x96
+            } else {
+                SqrtPriceMath::get_next_sqrt_price_from_input(
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    amount_remaining_less_fee,
+                    zero_for_one,
+                )?
+            }
+        } else {
+            amount_out = if zero_for_one {
+                SqrtPriceMath::get_amount1_delta(sqrt_ratio_target_x96, sqrt_ratio_current_x96, liquidity, false)?
+            } else {
+                SqrtPriceMath::get_amount0_delta(sqrt_ratio_current_x96, sqrt_ratio_target_x96, liquidity, false)?
+            };
+            if amount_remaining_abs >= amount_out {
+                sqrt_ratio_target_x96
+            } else {
+                SqrtPriceMath::get_next_sqrt_price_from_output(
+                    sqrt_ratio_current_x96,
+                    liquidity,
+                    amount_remaining_abs,
+                    zero_for_one,
+                )?
+            }
+        };
+
+        let max = sqrt_ratio_target_x96 == sqrt_ratio_next_x96;
+
+        // Get the input/output amounts
+        if zero_for_one {
+            if !(max && exact_in) {
+                amount_in = SqrtPriceMath::get_amount0_delta(sqrt_ratio_next_x96, sqrt_ratio_current_x96, liquidity, true)?;
+            }
+            if !max || exact_in {
+                amount_out = SqrtPriceMath::get_amount1_delta(sqrt_ratio_next_x96, sqrt_ratio_current_x96, liquidity, false)?;
+            }
+        } else {
+            if !(max && exact_in) {
+                amount_in = SqrtPriceMath::get_amount1_delta(sqrt_ratio_current_x96, sqrt_ratio_next_x96, liquidity, true)?;
+            }
+            if !max || exact_in {
+                amount_out = SqrtPriceMath::get_amount0_delta(sqrt_ratio_current_x96, sqrt_ratio_next_x96, liquidity, false)?;
+            }
+        }
+
+        // Cap the output amount to not exceed the remaining output amount
+        if !exact_in && amount_out > amount_remaining_abs {
+            amount_out = amount_remaining_abs;
+        }
+
+        let fee_amount = if exact_in && sqrt_ratio_next_x96 != sqrt_ratio_target_x96 {
+            // We didn't reach the target, so take the remainder of the maximum input as fee
+            amount_remaining_abs - amount_in
+        } else {
+            FullMath::mul_div_rounding_up(amount_in, U256::from(fee_pips), U256::from(1_000_000 - fee_pips))?
+        };
+
+        Ok((sqrt_ratio_next_x96, amount_in, amount_out, fee_amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::Q96;
+    use crate::math::TickMath;
+
+    const FEE: u32 = 3000;
+
+    #[test]
+    fn test_exact_input_capped_at_target() {
+        let price = U256::from(Q96);
+        let target = TickMath::get_sqrt_ratio_at_tick(60).unwrap();
+        let liquidity = U128::from(1_000_000_000u128);
+
+        let (next, amount_in, amount_out, fee_amount) =
+            SwapMath::compute_swap_step(price, target, liquidity, 1_000_000_000, FEE).unwrap();
+
+        // The step stops at the target and only charges the fee on what was consumed
+        assert_eq!(next, target);
+        assert!(amount_out > U256::zero());
+        assert!(amount_in + fee_amount < U256::from(1_000_000_000u128));
+        assert_eq!(
+            fee_amount,
+            FullMath::mul_div_rounding_up(amount_in, U256::from(FEE), U256::from(1_000_000 - FEE)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_exact_input_consumes_whole_amount() {
+        let price = U256::from(Q96);
+        let target = TickMath::get_sqrt_ratio_at_tick(-600).unwrap();
+        let liquidity = U128::from(1_000_000_000_000u128);
+
+        let (next, amount_in, amount_out, fee_amount) =
+            SwapMath::compute_swap_step(price, target, liquidity, 1_000_000, FEE).unwrap();
+
+        assert!(next < price && next > target);
+        assert_eq!(amount_in + fee_amount, U256::from(1_000_000u128));
+        assert!(amount_out < amount_in);
+    }
+
+    #[test]
+    fn test_exact_output_capped_at_remaining() {
+        let price = U256::from(Q96);
+        let target = TickMath::get_sqrt_ratio_at_tick(600).unwrap();
+        let liquidity = U128::from(1_000_000_000_000u128);
+
+        let (next, amount_in, amount_out, fee_amount) =
+            SwapMath::compute_swap_step(price, target, liquidity, -1_000_000, FEE).unwrap();
+
+        assert!(next > price && next < target);
+        assert_eq!(amount_out, U256::from(1_000_000u128));
+        assert!(amount_in > amount_out);
+        assert!(fee_amount > U256::zero());
+    }
+
+    #[test]
+    fn test_exact_output_capped_at_target() {
+        let price = U256::from(Q96);
+        let target = TickMath::get_sqrt_ratio_at_tick(-60).unwrap();
+        let liquidity = U128::from(1_000_000_000u128);
+
+        let (next, _, amount_out, _) =
+            SwapMath::compute_swap_step(price, target, liquidity, -1_000_000_000, FEE).unwrap();
+
+        assert_eq!(next, target);
+        assert!(amount_out < U256::from(1_000_000_000u128));
+    }
+
+    #[test]
+    fn test_zero_liquidity_moves_to_target_for_free() {
+        let price = U256::from(Q96);
+        let target = TickMath::get_sqrt_ratio_at_tick(-600).unwrap();
+
+        let (next, amount_in, amount_out, fee_amount) =
+            SwapMath::compute_swap_step(price, target, U128::zero(), 1_000_000, FEE).unwrap();
+
+        assert_eq!(next, target);
+        assert!(amount_in.is_zero() && amount_out.is_zero() && fee_amount.is_zero());
+    }
+}
@@ -3,6 +3,7 @@ use odra::{
 };
 use crate::{
     constants::{MIN_TICK, MAX_TICK, MIN_SQRT_RATIO, MAX_SQRT_RATIO_STR},
+    errors::Error,
     math::full_math::FullMath,
 };
 
@@ -19,8 +20,10 @@ impl TickMath {
     /// # Returns
     /// A Fixed point Q64.96 number representing the sqrt of the ratio of the two assets (token1/token0)
     /// at the given tick
-    pub fn get_sqrt_ratio_at_tick(tick: i32) -> U256 {
-        assert!(tick >= MIN_TICK && tick <= MAX_TICK, "Tick out of bounds");
+    pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U256, Error> {
+        if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+            return Err(Error::TickOutOfBounds);
+        }
 
         let abs_tick = if tick < 0 {
             (-(tick as i64)) as u32
@@ -105,9 +108,9 @@ impl TickMath {
         let remainder = ratio & U256::from((1u64 << 32) - 1);
 
         if remainder.is_zero() {
-            shifted
+            Ok(shifted)
         } else {
-            shifted + U256::one()
+            Ok(shifted + U256::one())
         }
     }
 
@@ -118,11 +121,10 @@ impl TickMath {
     ///
     /// # Returns
     /// The greatest tick for which the ratio is less than or equal to the input ratio
-    pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> i32 {
-        assert!(
-            sqrt_price_x96 >= U256::from(MIN_SQRT_RATIO) && sqrt_price_x96 < U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap(),
-            "sqrt_price_x96 out of bounds"
-        );
+    pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Result<i32, Error> {
+        if sqrt_price_x96 < U256::from(MIN_SQRT_RATIO) || sqrt_price_x96 >= U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap() {
+            return Err(Error::InvalidSqrtPrice);
+        }
 
         // Use binary search to find the tick
         // This is simpler and more reliable than complex logarithm calculations
@@ -131,10 +133,10 @@ impl TickMath {
 
         while tick_low < tick_high {
             let tick_mid = (tick_low + tick_high + 1) / 2;
-            let sqrt_ratio = Self::get_sqrt_ratio_at_tick(tick_mid);
+            let sqrt_ratio = Self::get_sqrt_ratio_at_tick(tick_mid)?;
 
             if sqrt_ratio == sqrt_price_x96 {
-                return tick_mid;
+                return Ok(tick_mid);
             } else if sqrt_ratio < sqrt_price_x96 {
                 tick_low = tick_mid;
             } else {
@@ -142,13 +144,14 @@ impl TickMath {
             }
         }
 
-        tick_low
+        Ok(tick_low)
     }
 
     /// Helper function to multiply two U256 numbers and right shift by 128
+    /// The constants are all below 2^128, so the result never exceeds `a`
     #[inline]
     fn mul_shift(a: U256, b: U256) -> U256 {
-        FullMath::mul_div(a, b, U256::one() << 128)
+        FullMath::mul_div(a, b, U256::one() << 128).unwrap_or(a)
     }
 }
 
@@ -158,19 +161,19 @@ mod tests {
 
     #[test]
     fn test_get_sqrt_ratio_at_tick_min() {
-        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(MIN_TICK);
+        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(MIN_TICK).unwrap();
         assert_eq!(sqrt_price, U256::from(MIN_SQRT_RATIO));
     }
 
     #[test]
     fn test_get_sqrt_ratio_at_tick_max() {
-        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(MAX_TICK);
+        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(MAX_TICK).unwrap();
         assert_eq!(sqrt_price,  U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap());
     }
 
     #[test]
     fn test_get_sqrt_ratio_at_tick_zero() {
-        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(0);
+        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(0).unwrap();
         // At tick 0, price = 1, so sqrt(price) * 2^96 = 2^96
         let expected = U256::from(1u128 << 96);
         assert_eq!(sqrt_price, expected);
@@ -185,8 +188,8 @@ mod tests {
         let test_ticks = vec![MIN_TICK, -100, 0, 100, MAX_TICK - 1];
 
         for tick in test_ticks {
-            let sqrt_price = TickMath::get_sqrt_ratio_at_tick(tick);
-            let recovered_tick = TickMath::get_tick_at_sqrt_ratio(sqrt_price);
+            let sqrt_price = TickMath::get_sqrt_ratio_at_tick(tick).unwrap();
+            let recovered_tick = TickMath::get_tick_at_sqrt_ratio(sqrt_price).unwrap();
 
             // Should be within 1 tick due to rounding
             assert!(
@@ -198,14 +201,21 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Tick out of bounds")]
     fn test_tick_too_low() {
-        TickMath::get_sqrt_ratio_at_tick(MIN_TICK - 1);
+        assert_eq!(TickMath::get_sqrt_ratio_at_tick(MIN_TICK - 1), Err(Error::TickOutOfBounds));
     }
 
     #[test]
-    #[should_panic(expected = "Tick out of bounds")]
     fn test_tick_too_high() {
-        TickMath::get_sqrt_ratio_at_tick(MAX_TICK + 1);
+        assert_eq!(TickMath::get_sqrt_ratio_at_tick(MAX_TICK + 1), Err(Error::TickOutOfBounds));
+    }
+
+    #[test]
+    fn test_sqrt_price_out_of_bounds() {
+        assert_eq!(TickMath::get_tick_at_sqrt_ratio(U256::zero()), Err(Error::InvalidSqrtPrice));
+        assert_eq!(
+            TickMath::get_tick_at_sqrt_ratio(U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap()),
+            Err(Error::InvalidSqrtPrice)
+        );
     }
 }
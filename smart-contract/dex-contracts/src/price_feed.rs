@@ -0,0 +1,359 @@
+use odra::{
+    casper_types::{U256, U128},
+    prelude::*,
+    ContractRef,
+};
+use odra_modules::{access::Role, erc20::Erc20ContractRef};
+use crate::{
+    access::{Roles, ORACLE_ADMIN_ROLE},
+    errors::Error,
+    types::events::{PriceFeedRemoved, PriceFeedSet},
+    unified_dex::UnifiedDexContractRef,
+};
+
+/// Price feed exposing UnifiedDex TWAPs through a single read call
+///
+/// Each (base, quote) feed reads the arithmetic mean tick of one pool, or of two pools chained
+/// through an intermediate token (e.g. TOKEN → WCSPR → USDC), over its configured window.
+/// The pools must hold enough observations to cover the window (see `increase_observation_cardinality`).
+#[odra::module(events = [PriceFeedSet, PriceFeedRemoved], errors = Error)]
+pub struct PriceFeed {
+    roles: SubModule<Roles>,
+    dex_address: Var<Address>,
+    feeds: Mapping<(Address, Address), FeedConfig>,  // (base, quote) -> feed configuration
+}
+
+/// Configuration of a (base, quote) price feed
+#[odra::odra_type]
+pub struct FeedConfig {
+    pub fee: u32,                       // Fee tier of the base/quote pool, or base/intermediate when chained
+    pub window: u32,                    // TWAP window in seconds
+    pub intermediate: Option<Address>,  // Token to route through when there is no direct pool
+    pub intermediate_fee: u32,          // Fee tier of the intermediate/quote pool (ignored when not chained)
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+}
+
+#[odra::module]
+impl PriceFeed {
+    #[odra(init)]
+    pub fn init(&mut self, dex_address: Address) {
+        let caller = self.env().caller();
+        self.roles.init(caller);
+        self.dex_address.set(dex_address);
+    }
+
+    /// Configure the feed for `base` priced in `quote` (oracle admin role)
+    ///
+    /// The pools are checked to exist, and token decimals are read once here.
+    pub fn set_feed(
+        &mut self,
+        base: Address,
+        quote: Address,
+        fee: u32,
+        window: u32,
+        intermediate: Option<Address>,
+        intermediate_fee: u32,
+    ) {
+        self.roles.assert_role(ORACLE_ADMIN_ROLE);
+        if base == quote {
+            self.env().revert(Error::IdenticalTokens);
+        }
+        if window == 0 {
+            self.env().revert(Error::ZeroPeriod);
+        }
+
+        let dex = self.dex();
+        match intermediate {
+            Some(intermediate) => {
+                if intermediate == base || intermediate == quote {
+                    self.env().revert(Error::InvalidIntermediateToken);
+                }
+                if dex.get_pool(base, intermediate, fee).is_none() {
+                    self.env().revert(Error::PoolDoesNotExist);
+                }
+                if dex.get_pool(intermediate, quote, intermediate_fee).is_none() {
+                    self.env().revert(Error::PoolDoesNotExist);
+                }
+            }
+            None => {
+                if dex.get_pool(base, quote, fee).is_none() {
+                    self.env().revert(Error::PoolDoesNotExist);
+                }
+            }
+        }
+
+        let base_decimals = Erc20ContractRef::new(self.env(), base).decimals();
+        let quote_decimals = Erc20ContractRef::new(self.env(), quote).decimals();
+        if base_decimals > 38 {
+            self.env().revert(Error::UnsupportedDecimals);
+        }
+
+        self.feeds.set(&(base, quote), FeedConfig {
+            fee,
+            window,
+            intermediate,
+            intermediate_fee,
+            base_decimals,
+            quote_decimals,
+        });
+
+        self.env().emit_event(PriceFeedSet {
+            base,
+            quote,
+            fee,
+            window,
+            intermediate,
+            intermediate_fee,
+        });
+    }
+
+    /// Remove the feed for `base` priced in `quote` (oracle admin role)
+    pub fn remove_feed(&mut self, base: Address, quote: Address) {
+        self.roles.assert_role(ORACLE_ADMIN_ROLE);
+        if self.feeds.get(&(base, quote)).is_none() {
+            self.env().revert(Error::FeedNotConfigured);
+        }
+
+        // Mapping has no removal, so the feed is disabled by zeroing its window
+        self.feeds.set(&(base, quote), FeedConfig {
+            fee: 0,
+            window: 0,
+            intermediate: None,
+            intermediate_fee: 0,
+            base_decimals: 0,
+            quote_decimals: 0,
+        });
+
+        self.env().emit_event(PriceFeedRemoved { base, quote });
+    }
+
+    /// Time-weighted price of one whole `base` token in `quote`, with `decimals(base, quote)` decimals
+    pub fn latest_price(&self, base: Address, quote: Address) -> U256 {
+        let config = self.get_config(base, quote);
+        let dex = self.dex();
+        let one_base = U256::from(10u128.pow(config.base_decimals as u32));
+
+        match config.intermediate {
+            Some(intermediate) => {
+                let intermediate_amount = self.quote_twap(&dex, base, intermediate, config.fee, config.window, one_base);
+                self.quote_twap(&dex, intermediate, quote, config.intermediate_fee, config.window, intermediate_amount)
+            }
+            None => self.quote_twap(&dex, base, quote, config.fee, config.window, one_base),
+        }
+    }
+
+    /// Number of decimals of `latest_price(base, quote)`, which is the decimals of `quote`
+    pub fn decimals(&self, base: Address, quote: Address) -> u8 {
+        self.get_config(base, quote).quote_decimals
+    }
+
+    /// Timestamp, in seconds, of the most recent oracle write backing the feed
+    ///
+    /// For chained feeds this is the older of the two pools' latest writes.
+    pub fn updated_at(&self, base: Address, quote: Address) -> u64 {
+        let config = self.get_config(base, quote);
+        let dex = self.dex();
+
+        let updated_at = self.last_observation_time(&dex, base, quote, config.fee, config.intermediate);
+        match config.intermediate {
+            Some(intermediate) => updated_at.min(self.last_observation_time(
+                &dex, intermediate, quote, config.intermediate_fee, None,
+            )),
+            None => updated_at,
+        }
+    }
+
+    /// Configuration of the (base, quote) feed, if any
+    pub fn get_feed(&self, base: Address, quote: Address) -> Option<FeedConfig> {
+        self.feeds.get(&(base, quote)).filter(|config| config.window > 0)
+    }
+
+    /// TWAP window of the (base, quote) feed, in seconds
+    pub fn get_window(&self, base: Address, quote: Address) -> u32 {
+        self.get_config(base, quote).window
+    }
+
+    pub fn get_dex_address(&self) -> Address {
+        self.dex_address.get().unwrap()
+    }
+
+    delegate! {
+        to self.roles {
+            /// Whether `address` holds `role` (see `crate::access` for the roles)
+            fn has_role(&self, role: Role, address: Address) -> bool;
+            /// Account that grants and revokes roles
+            fn get_owner(&self) -> Address;
+            /// Account nominated by `transfer_ownership` that has not accepted yet, if any
+            fn get_pending_owner(&self) -> Option<Address>;
+            /// Grant `role` to `address` (owner only)
+            fn grant_role(&mut self, role: Role, address: Address);
+            /// Revoke `role` from `address` (owner only)
+            fn revoke_role(&mut self, role: Role, address: Address);
+            /// Give up `role` held by the caller
+            fn renounce_role(&mut self, role: Role);
+            /// Nominate `new_owner`, who takes over once they call `accept_ownership` (owner only)
+            fn transfer_ownership(&mut self, new_owner: Address);
+            /// Take over ownership and the old owner's roles (pending owner only)
+            fn accept_ownership(&mut self);
+        }
+    }
+}
+
+// Internal functions
+impl PriceFeed {
+    fn dex(&self) -> UnifiedDexContractRef {
+        UnifiedDexContractRef::new(self.env(), self.dex_address.get().unwrap())
+    }
+
+    fn get_config(&self, base: Address, quote: Address) -> FeedConfig {
+        self.get_feed(base, quote).unwrap_or_revert_with(self, Error::FeedNotConfigured)
+    }
+
+    /// Converts `amount` of `base` into `quote` at the pool's mean tick over `window`
+    fn quote_twap(
+        &self,
+        dex: &UnifiedDexContractRef,
+        base: Address,
+        quote: Address,
+        fee: u32,
+        window: u32,
+        amount: U256,
+    ) -> U256 {
+        let (mean_tick, _) = dex.consult(base, quote, fee, window);
+
+        if amount > U256::from(u128::MAX) {
+            self.env().revert(Error::AmountOverflow);
+        }
+        dex.get_quote_at_tick(mean_tick, U128::from(amount.as_u128()), base, quote)
+    }
+
+    /// Timestamp of the latest observation of the base/quote pool, or base/intermediate when chained
+    fn last_observation_time(
+        &self,
+        dex: &UnifiedDexContractRef,
+        base: Address,
+        quote: Address,
+        fee: u32,
+        intermediate: Option<Address>,
+    ) -> u64 {
+        let pooled = intermediate.unwrap_or(quote);
+        let pool = dex.get_pool(base, pooled, fee).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        let observation = dex
+            .get_observation(base, pooled, fee, pool.slot0.observation_index)
+            .unwrap_or_revert_with(self, Error::PoolNotInitialized);
+        observation.block_timestamp as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_fixtures::{create_pool, deploy_dex, deploy_tokens, FEE},
+        types::tick::I128,
+        unified_dex::UnifiedDexHostRef,
+    };
+    use odra::host::{Deployer, HostEnv};
+    use odra_modules::erc20::Erc20HostRef;
+
+    /// Deploys the DEX with two price-1 pools, A/B and B/C, and a price feed in front of it
+    fn setup() -> (HostEnv, UnifiedDexHostRef, PriceFeedHostRef, [Erc20HostRef; 3]) {
+        let (env, mut dex) = deploy_dex();
+        let feed = PriceFeed::deploy(&env, PriceFeedInitArgs { dex_address: dex.address() });
+        let mut tokens = deploy_tokens(&env, ["TKA", "TKB", "TKC"]);
+        for token in tokens.iter_mut() {
+            token.approve(&dex.address(), &U256::MAX);
+        }
+
+        // Tokens are sorted, so both pairs are already in pool order
+        for (a, b) in [(0, 1), (1, 2)] {
+            let (token0, token1) = (tokens[a].address(), tokens[b].address());
+            create_pool(&mut dex, token0, token1);
+            dex.increase_observation_cardinality(token0, token1, FEE, 10);
+            dex.mint(
+                token0, token1, FEE, env.get_account(0),
+                -6000, 6000, U128::from(10u128.pow(24)), U256::zero(), U256::zero(),
+            );
+        }
+
+        (env, dex, feed, tokens)
+    }
+
+    /// Pushes the A/B price away from 1 and lets it sit for the whole window
+    fn move_price(env: &HostEnv, dex: &mut UnifiedDexHostRef, a: Address, b: Address) -> i32 {
+        env.advance_block_time(1_000);
+        dex.swap(a, b, FEE, env.get_account(0), true, I128::from_i128(10i128.pow(23)), U256::zero());
+        env.advance_block_time(600_000);
+        dex.get_twap(a, b, FEE, 600, 0).unwrap()
+    }
+
+    #[test]
+    fn test_direct_feed_reads_twap() {
+        let (env, mut dex, mut feed, tokens) = setup();
+        let (a, b) = (tokens[0].address(), tokens[1].address());
+        let tick = move_price(&env, &mut dex, a, b);
+
+        feed.set_feed(a, b, FEE, 600, None, 0);
+        let event: PriceFeedSet = env.get_event(&feed, -1).unwrap();
+        assert_eq!((event.base, event.quote, event.window), (a, b, 600));
+
+        // The whole window sits at the post-swap tick
+        let one = U128::from(10u128.pow(18));
+        assert_eq!(feed.latest_price(a, b), dex.get_quote_at_tick(tick, one, a, b));
+        assert!(feed.latest_price(a, b) < U256::from(one.as_u128()));
+        assert_eq!(feed.decimals(a, b), 18);
+        assert_eq!(feed.get_window(a, b), 600);
+
+        // The reverse feed reads the same pool at the negated tick
+        feed.set_feed(b, a, FEE, 600, None, 0);
+        assert_eq!(feed.latest_price(b, a), dex.get_quote_at_tick(-tick, one, b, a));
+        assert!(feed.latest_price(b, a) > U256::from(one.as_u128()));
+
+        // The swap wrote the latest observation
+        assert_eq!(feed.updated_at(a, b), 1);
+    }
+
+    #[test]
+    fn test_chained_feed_routes_through_intermediate() {
+        let (env, mut dex, mut feed, tokens) = setup();
+        let (a, b, c) = (tokens[0].address(), tokens[1].address(), tokens[2].address());
+        move_price(&env, &mut dex, a, b);
+
+        feed.set_feed(a, b, FEE, 600, None, 0);
+        feed.set_feed(a, c, FEE, 600, Some(b), FEE);
+
+        // B/C sits at price 1, so A priced in C matches A priced in B up to rounding
+        let direct = feed.latest_price(a, b);
+        let chained = feed.latest_price(a, c);
+        assert!(chained <= direct && chained + U256::one() >= direct);
+
+        // B/C has only its initialization write, which is older than A/B's swap
+        assert_eq!(feed.updated_at(a, c), 0);
+    }
+
+    #[test]
+    fn test_feed_configuration_needs_oracle_admin() {
+        let (env, _dex, mut feed, tokens) = setup();
+        let (a, b, c) = (tokens[0].address(), tokens[1].address(), tokens[2].address());
+
+        feed.set_feed(a, b, FEE, 600, None, 0);
+        feed.remove_feed(a, b);
+        assert!(feed.get_feed(a, b).is_none());
+        assert_eq!(feed.try_latest_price(a, b), Err(Error::FeedNotConfigured.into()));
+
+        // Only oracle admins may configure feeds
+        let admin = env.get_account(1);
+        env.set_caller(admin);
+        assert_eq!(feed.try_set_feed(a, b, FEE, 600, None, 0), Err(Error::Unauthorized.into()));
+        env.set_caller(env.get_account(0));
+        feed.grant_role(ORACLE_ADMIN_ROLE, admin);
+        env.set_caller(admin);
+        feed.set_feed(a, b, FEE, 600, None, 0);
+        assert!(feed.get_feed(a, b).is_some());
+
+        // No direct A/C pool
+        assert_eq!(feed.try_set_feed(a, c, FEE, 600, None, 0), Err(Error::PoolDoesNotExist.into()));
+        assert_eq!(feed.try_set_feed(a, b, FEE, 0, None, 0), Err(Error::ZeroPeriod.into()));
+    }
+}
@@ -0,0 +1,47 @@
+//! Token reserves held by the UnifiedDex on behalf of each pool
+//!
+//! All pools share the DEX's token balances. Payments are checked against the part of a
+//! balance no pool accounts for yet, so a transfer made from a callback can only pay for one
+//! operation, even when operations in several pools of the same token are nested.
+
+use odra::{casper_types::U256, prelude::*, ContractRef};
+use odra_modules::erc20::Erc20ContractRef;
+use crate::errors::Error;
+
+/// Per-pool and per-token reserves, embedded by `UnifiedDex`
+#[odra::module(errors = Error)]
+pub struct Reserves {
+    token_reserves: Mapping<Address, U256>,  // token -> sum of every pool's reserve of it
+    pool_reserves: Mapping<([u8; 32], Address), U256>,  // (pool_id, token) -> amount held for that pool
+}
+
+impl Reserves {
+    /// Amount of `token` held for the pool
+    pub fn pool_reserve(&self, pool_id: [u8; 32], token: Address) -> U256 {
+        self.pool_reserves.get_or_default(&(pool_id, token))
+    }
+
+    /// Amount of `token` the DEX holds beyond every pool's reserve
+    pub fn unaccounted(&self, token: Address) -> U256 {
+        let balance = Erc20ContractRef::new(self.env(), token).balance_of(&self.env().self_address());
+        balance.saturating_sub(self.token_reserves.get_or_default(&token))
+    }
+
+    /// Add `amount` to the pool's reserve
+    pub fn credit(&mut self, pool_id: [u8; 32], token: Address, amount: U256) {
+        if !amount.is_zero() {
+            self.token_reserves.add(&token, amount);
+            self.pool_reserves.add(&(pool_id, token), amount);
+        }
+    }
+
+    /// Take `amount` out of the pool's reserve, reverting if the pool holds less
+    pub fn debit(&mut self, pool_id: [u8; 32], token: Address, amount: U256) {
+        let reserve = self.pool_reserve(pool_id, token);
+        if reserve < amount {
+            self.env().revert(Error::InsufficientReserves);
+        }
+        self.pool_reserves.set(&(pool_id, token), reserve - amount);
+        self.token_reserves.subtract(&token, amount);
+    }
+}
@@ -1,15 +1,26 @@
 use odra::{
-    casper_types::U256,
+    casper_types::{
+        bytesrepr::{Bytes, FromBytes, ToBytes},
+        U256,
+    },
     prelude::*,
     ContractRef,
 };
-use crate::unified_dex::UnifiedDexContractRef;
+use odra_modules::erc20::Erc20ContractRef;
+use crate::{errors::Error, types::tick::I128, unified_dex::UnifiedDexContractRef};
 
 /// Router for multi-hop swaps across multiple pools
 /// Enables trading pairs without direct liquidity (e.g., WCSPR → USDC → DAI)
-#[odra::module]
+///
+/// Swaps use the DEX callback flow: the caller approves the router, which pays each
+/// hop's input from `swap_callback`. Exact-input hops run in order and are paid by the
+/// caller for the first hop, by the router afterwards. Exact-output hops run from the last
+/// one backwards: each hop's callback runs the hop before it with the DEX as recipient, and
+/// the caller pays the first hop of the path.
+#[odra::module(errors = Error)]
 pub struct Router {
     dex_address: Var<Address>,
+    amount_in_cached: Var<U256>,  // Input paid by the caller on the first hop of an exact-output path
 }
 
 /// Parameters for multi-hop swap with exact input
@@ -34,6 +45,42 @@ pub struct ExactOutputParams {
     pub amount_in_maximum: U256,
 }
 
+/// Parameters for a single-pool swap with exact input
+#[odra::odra_type]
+pub struct ExactInputSingleParams {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub fee: u32,
+    pub recipient: Address,
+    pub deadline: u64,
+    pub amount_in: U256,
+    pub amount_out_minimum: U256,
+    pub sqrt_price_limit_x96: U256,   // Zero for no price limit
+}
+
+/// Parameters for a single-pool swap with exact output
+#[odra::odra_type]
+pub struct ExactOutputSingleParams {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub fee: u32,
+    pub recipient: Address,
+    pub deadline: u64,
+    pub amount_out: U256,
+    pub amount_in_maximum: U256,
+    pub sqrt_price_limit_x96: U256,   // Zero for no price limit
+}
+
+/// Data passed through the DEX to `swap_callback`
+#[odra::odra_type]
+struct SwapCallbackData {
+    token0: Address,
+    token1: Address,
+    payer: Address,
+    path: Vec<Address>,  // Exact output only: [hop's tokenIn, ..., tokenIn] of the hops left to run
+    fees: Vec<u32>,      // Fees of the hops left to run
+}
+
 #[odra::module]
 impl Router {
     #[odra(init)]
@@ -41,6 +88,78 @@ impl Router {
         self.dex_address.set(dex_address);
     }
 
+    /// Swap an exact amount of `token_in` for as much `token_out` as possible in one pool
+    /// Tokens may be given in any order; the caller pays through `swap_callback`
+    pub fn swap_exact_input_single(&mut self, params: ExactInputSingleParams) -> U256 {
+        if self.env().get_block_time() > params.deadline {
+            self.env().revert(Error::Expired);
+        }
+
+        let (token0, token1, zero_for_one) = Self::sort_tokens(params.token_in, params.token_out);
+        let dex_address = self.dex_address.get().unwrap();
+        let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
+
+        let (amount0, amount1) = dex.swap_with_callback(
+            token0,
+            token1,
+            params.fee,
+            params.recipient,
+            zero_for_one,
+            I128::from_i128(self.amount_to_i128(params.amount_in)),
+            params.sqrt_price_limit_x96,
+            Self::callback_data(token0, token1, self.env().caller()),
+        );
+
+        let amount_out = if zero_for_one {
+            U256::from(amount1.abs.as_u128())
+        } else {
+            U256::from(amount0.abs.as_u128())
+        };
+        if amount_out < params.amount_out_minimum {
+            self.env().revert(Error::SlippageExceeded);
+        }
+
+        amount_out
+    }
+
+    /// Swap as little `token_in` as possible for an exact amount of `token_out` in one pool
+    /// Tokens may be given in any order; the caller pays through `swap_callback`
+    pub fn swap_exact_output_single(&mut self, params: ExactOutputSingleParams) -> U256 {
+        if self.env().get_block_time() > params.deadline {
+            self.env().revert(Error::Expired);
+        }
+
+        let (token0, token1, zero_for_one) = Self::sort_tokens(params.token_in, params.token_out);
+        let dex_address = self.dex_address.get().unwrap();
+        let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
+
+        let (amount0, amount1) = dex.swap_with_callback(
+            token0,
+            token1,
+            params.fee,
+            params.recipient,
+            zero_for_one,
+            I128::from_i128(-self.amount_to_i128(params.amount_out)),  // Negative = exact output
+            params.sqrt_price_limit_x96,
+            Self::callback_data(token0, token1, self.env().caller()),
+        );
+
+        let (amount_in, amount_out_received) = if zero_for_one {
+            (U256::from(amount0.abs.as_u128()), U256::from(amount1.abs.as_u128()))
+        } else {
+            (U256::from(amount1.abs.as_u128()), U256::from(amount0.abs.as_u128()))
+        };
+        // Without a price limit the pool must deliver the full amount
+        if params.sqrt_price_limit_x96.is_zero() && amount_out_received != params.amount_out {
+            self.env().revert(Error::SlippageExceeded);
+        }
+        if amount_in > params.amount_in_maximum {
+            self.env().revert(Error::SlippageExceeded);
+        }
+
+        amount_in
+    }
+
     /// Execute multi-hop swap with exact input
     /// Example: Swap 100 WCSPR for at least 95 DAI via USDC
     /// path = [WCSPR, USDC, DAI]
@@ -50,11 +169,14 @@ impl Router {
         params: ExactInputParams,
     ) -> U256 {
         // Validate deadline
-        assert!(self.env().get_block_time() <= params.deadline, "Transaction too old");
+        if self.env().get_block_time() > params.deadline {
+            self.env().revert(Error::Expired);
+        }
 
         // Validate path and fees
-        assert!(params.path.len() >= 2, "Path too short");
-        assert!(params.fees.len() == params.path.len() - 1, "Fees length mismatch");
+        if params.path.len() < 2 || params.fees.len() != params.path.len() - 1 {
+            self.env().revert(Error::InvalidPath);
+        }
 
         let dex_address = self.dex_address.get().unwrap();
         let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
@@ -83,27 +205,37 @@ impl Router {
                 self.env().self_address()
             };
 
+            // The caller pays the first hop, the router pays later hops from its balance
+            let payer = if i == 0 {
+                self.env().caller()
+            } else {
+                self.env().self_address()
+            };
+
             // Execute swap
-            let (amount0, amount1) = dex.swap(
+            let (amount0, amount1) = dex.swap_with_callback(
                 token0,
                 token1,
                 fee,
                 recipient,
                 zero_for_one,
-                amount_out.as_u128() as i64,  // Use output from previous swap as input
+                I128::from_i128(self.amount_to_i128(amount_out)),  // Use output from previous swap as input
                 U256::zero(),  // No price limit for router
+                Self::callback_data(token0, token1, payer),
             );
 
             // Update amount for next hop
             amount_out = if zero_for_one {
-                U256::from((-amount1) as u128)  // Received token1
+                U256::from(amount1.abs.as_u128())  // Received token1
             } else {
-                U256::from((-amount0) as u128)  // Received token0
+                U256::from(amount0.abs.as_u128())  // Received token0
             };
         }
 
         // Validate minimum output
-        assert!(amount_out >= params.amount_out_minimum, "Insufficient output amount");
+        if amount_out < params.amount_out_minimum {
+            self.env().revert(Error::SlippageExceeded);
+        }
 
         amount_out
     }
@@ -117,60 +249,25 @@ impl Router {
         params: ExactOutputParams,
     ) -> U256 {
         // Validate deadline
-        assert!(self.env().get_block_time() <= params.deadline, "Transaction too old");
+        if self.env().get_block_time() > params.deadline {
+            self.env().revert(Error::Expired);
+        }
 
         // Validate path and fees
-        assert!(params.path.len() >= 2, "Path too short");
-        assert!(params.fees.len() == params.path.len() - 1, "Fees length mismatch");
-
-        let dex_address = self.dex_address.get().unwrap();
-        let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
-
-        let mut amount_in = params.amount_out;
-
-        // Execute swaps in REVERSE order (from output to input)
-        for i in (0..params.fees.len()).rev() {
-            let token_out = params.path[i];
-            let token_in = params.path[i + 1];
-            let fee = params.fees[i];
-
-            // Order tokens for pool lookup
-            let (token0, token1) = if token_in < token_out {
-                (token_in, token_out)
-            } else {
-                (token_out, token_in)
-            };
-            let zero_for_one = token_in == token0;
-
-            // For first swap (last in execution), send to recipient
-            // For others, send to this contract
-            let recipient = if i == 0 {
-                params.recipient
-            } else {
-                self.env().self_address()
-            };
-
-            // Execute swap with negative amount (exact output)
-            let (amount0, amount1) = dex.swap(
-                token0,
-                token1,
-                fee,
-                recipient,
-                zero_for_one,
-                -(amount_in.as_u128() as i64),  // Negative = exact output
-                U256::zero(),  // No price limit for router
-            );
-
-            // Update amount needed for next hop (working backwards)
-            amount_in = if zero_for_one {
-                U256::from(amount0 as u128)  // Need to provide token0
-            } else {
-                U256::from(amount1 as u128)  // Need to provide token1
-            };
+        if params.path.len() < 2 || params.fees.len() != params.path.len() - 1 {
+            self.env().revert(Error::InvalidPath);
         }
 
+        // The last hop runs first; the callbacks run the others and record the caller's input
+        let caller = self.env().caller();
+        self.exact_output_hop(params.amount_out, params.recipient, params.path, params.fees, caller);
+        let amount_in = self.amount_in_cached.get_or_default();
+        self.amount_in_cached.set(U256::zero());
+
         // Validate maximum input
-        assert!(amount_in <= params.amount_in_maximum, "Excessive input amount");
+        if amount_in > params.amount_in_maximum {
+            self.env().revert(Error::SlippageExceeded);
+        }
 
         amount_in
     }
@@ -182,8 +279,9 @@ impl Router {
         fees: Vec<u32>,
         amount_in: U256,
     ) -> U256 {
-        assert!(path.len() >= 2, "Path too short");
-        assert!(fees.len() == path.len() - 1, "Fees length mismatch");
+        if path.len() < 2 || fees.len() != path.len() - 1 {
+            self.env().revert(Error::InvalidPath);
+        }
 
         let dex_address = self.dex_address.get().unwrap();
         let dex = UnifiedDexContractRef::new(self.env(), dex_address);
@@ -215,15 +313,126 @@ impl Router {
         amount_out
     }
 
+    /// DEX swap callback: pays the input owed to the pool on behalf of the payer
+    /// encoded in `data`. Only the DEX may call this.
+    pub fn swap_callback(&mut self, amount0_delta: I128, amount1_delta: I128, data: Bytes) {
+        let dex_address = self.dex_address.get().unwrap();
+        if self.env().caller() != dex_address {
+            self.env().revert(Error::Unauthorized);
+        }
+
+        let (data, _) = SwapCallbackData::from_bytes(&data).unwrap_or_revert_with(self, Error::InvalidCallbackData);
+        let (token, amount) = if amount0_delta.as_i128() > 0 {
+            (data.token0, amount0_delta)
+        } else {
+            (data.token1, amount1_delta)
+        };
+        if amount.as_i128() <= 0 {
+            return;
+        }
+        let amount = U256::from(amount.abs.as_u128());
+
+        // An exact-output hop is paid by the previous hop, which sends its output to the DEX
+        if data.path.len() > 1 {
+            self.exact_output_hop(amount, dex_address, data.path, data.fees, data.payer);
+            return;
+        }
+        if data.path.len() == 1 {
+            self.amount_in_cached.set(amount);
+        }
+
+        let mut token_contract = Erc20ContractRef::new(self.env(), token);
+        let payer = data.payer;
+        if payer == self.env().self_address() {
+            token_contract.transfer(&dex_address, &amount);
+        } else {
+            token_contract.transfer_from(&payer, &dex_address, &amount);
+        }
+    }
+
     // Getter
     pub fn get_dex_address(&self) -> Address {
         self.dex_address.get().unwrap()
     }
+
+    /// Order a token pair for pool lookup, returning whether `token_in` is token0
+    fn sort_tokens(token_in: Address, token_out: Address) -> (Address, Address, bool) {
+        if token_in < token_out {
+            (token_in, token_out, true)
+        } else {
+            (token_out, token_in, false)
+        }
+    }
+
+    /// Encode the pool tokens and the account paying for a hop for `swap_callback`
+    fn callback_data(token0: Address, token1: Address, payer: Address) -> Bytes {
+        Self::exact_output_callback_data(token0, token1, payer, Vec::new(), Vec::new())
+    }
+
+    /// Encode a hop's `swap_callback` data along with the exact-output hops left to run
+    fn exact_output_callback_data(
+        token0: Address,
+        token1: Address,
+        payer: Address,
+        path: Vec<Address>,
+        fees: Vec<u32>,
+    ) -> Bytes {
+        Bytes::from(SwapCallbackData { token0, token1, payer, path, fees }.to_bytes().unwrap())
+    }
+
+    /// Swap for exactly `amount_out` of `path[0]` in the pool of `path[0]`, `path[1]` and
+    /// `fees[0]`, leaving the rest of the path to `swap_callback`
+    ///
+    /// # Returns
+    /// The amount of `path[1]` the hop took
+    fn exact_output_hop(
+        &mut self,
+        amount_out: U256,
+        recipient: Address,
+        path: Vec<Address>,
+        fees: Vec<u32>,
+        payer: Address,
+    ) -> U256 {
+        let (token0, token1, zero_for_one) = Self::sort_tokens(path[1], path[0]);
+        let dex_address = self.dex_address.get().unwrap();
+        let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
+
+        let (amount0, amount1) = dex.swap_with_callback(
+            token0,
+            token1,
+            fees[0],
+            recipient,
+            zero_for_one,
+            I128::from_i128(-self.amount_to_i128(amount_out)),  // Negative = exact output
+            U256::zero(),  // No price limit for router
+            Self::exact_output_callback_data(token0, token1, payer, path[1..].to_vec(), fees[1..].to_vec()),
+        );
+
+        let (amount_in, amount_out_received) = if zero_for_one {
+            (U256::from(amount0.abs.as_u128()), U256::from(amount1.abs.as_u128()))
+        } else {
+            (U256::from(amount1.abs.as_u128()), U256::from(amount0.abs.as_u128()))
+        };
+        // Without a price limit the pool must deliver the full amount
+        if amount_out_received != amount_out {
+            self.env().revert(Error::SlippageExceeded);
+        }
+
+        amount_in
+    }
+
+    /// Convert a swap amount to the DEX's signed amount, reverting if it does not fit
+    fn amount_to_i128(&self, amount: U256) -> i128 {
+        i128::try_from(amount).ok().unwrap_or_revert_with(self, Error::AmountOverflow)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_fixtures::{add_liquidity, create_pool, deploy_dex, deploy_tokens, FEE};
+    use odra::host::{Deployer, HostEnv};
+    use odra_modules::erc20::Erc20HostRef;
 
     // Note: These would be integration tests requiring deployed DEX
     // For now, just structural tests
@@ -231,7 +440,7 @@ mod tests {
     #[test]
     fn test_path_validation() {
         // Path must have at least 2 tokens
-        let short_path = vec![];
+        let short_path: Vec<Address> = vec![];
         assert!(short_path.len() < 2);
 
         let valid_path = vec![
@@ -247,4 +456,207 @@ mod tests {
         let fees_len = 2; // Should have 2 fees
         assert_eq!(fees_len, path_len - 1);
     }
+
+    /// Deploys the DEX with one funded 0.3% pool at price 1, and a router in front of it
+    fn setup() -> (HostEnv, RouterHostRef, Erc20HostRef, Erc20HostRef) {
+        let (env, mut dex) = deploy_dex();
+        let router = Router::deploy(&env, RouterInitArgs { dex_address: dex.address() });
+        let [mut token0, mut token1] = deploy_tokens(&env, ["TKA", "TKB"]);
+        token0.approve(&dex.address(), &U256::MAX);
+        token1.approve(&dex.address(), &U256::MAX);
+        create_pool(&mut dex, token0.address(), token1.address());
+        add_liquidity(&mut dex, env.get_account(0), token0.address(), token1.address());
+
+        (env, router, token0, token1)
+    }
+
+    #[test]
+    fn test_exact_input_paid_through_callback() {
+        let (env, mut router, mut token0, token1) = setup();
+        let trader = env.get_account(1);
+        let amount_in = U256::from(1_000_000u128);
+
+        // The trader only approves the router; the router holds no tokens
+        token0.transfer(&trader, &amount_in);
+        env.set_caller(trader);
+        token0.approve(&router.address(), &amount_in);
+
+        let amount_out = router.swap_exact_input_multi_hop(ExactInputParams {
+            path: vec![token0.address(), token1.address()],
+            fees: vec![3000],
+            recipient: trader,
+            deadline: u64::MAX,
+            amount_in,
+            amount_out_minimum: U256::one(),
+        });
+
+        assert!(!amount_out.is_zero());
+        assert!(token0.balance_of(&trader).is_zero());
+        assert_eq!(token1.balance_of(&trader), amount_out);
+        assert!(token0.balance_of(&router.address()).is_zero());
+    }
+
+    #[test]
+    fn test_exact_input_single_accepts_unordered_tokens() {
+        let (env, mut router, mut token0, mut token1) = setup();
+        let trader = env.get_account(1);
+        let amount_in = U256::from(1_000_000u128);
+
+        token1.transfer(&trader, &amount_in);
+        env.set_caller(trader);
+        token1.approve(&router.address(), &amount_in);
+
+        // token_in is token1 here, so the router swaps one-for-zero
+        let amount_out = router.swap_exact_input_single(ExactInputSingleParams {
+            token_in: token1.address(),
+            token_out: token0.address(),
+            fee: 3000,
+            recipient: trader,
+            deadline: u64::MAX,
+            amount_in,
+            amount_out_minimum: U256::one(),
+            sqrt_price_limit_x96: U256::zero(),
+        });
+
+        assert!(!amount_out.is_zero());
+        assert!(token1.balance_of(&trader).is_zero());
+        assert_eq!(token0.balance_of(&trader), amount_out);
+
+        // An unreachable minimum reverts the whole swap
+        token0.approve(&router.address(), &amount_out);
+        assert!(router
+            .try_swap_exact_input_single(ExactInputSingleParams {
+                token_in: token0.address(),
+                token_out: token1.address(),
+                fee: 3000,
+                recipient: trader,
+                deadline: u64::MAX,
+                amount_in: amount_out,
+                amount_out_minimum: amount_out,
+                sqrt_price_limit_x96: U256::zero(),
+            })
+            .is_err());
+
+        assert_eq!(
+            router.try_swap_exact_input_single(ExactInputSingleParams {
+                token_in: token0.address(),
+                token_out: token1.address(),
+                fee: 3000,
+                recipient: trader,
+                deadline: u64::MAX,
+                amount_in: U256::from(u128::MAX),
+                amount_out_minimum: U256::zero(),
+                sqrt_price_limit_x96: U256::zero(),
+            }),
+            Err(Error::AmountOverflow.into())
+        );
+    }
+
+    #[test]
+    fn test_exact_output_single_respects_maximum_and_deadline() {
+        let (env, mut router, mut token0, token1) = setup();
+        let trader = env.get_account(1);
+        let amount_out = U256::from(1_000_000u128);
+
+        token0.transfer(&trader, &U256::from(2_000_000u128));
+        env.set_caller(trader);
+        token0.approve(&router.address(), &U256::from(2_000_000u128));
+
+        let params = ExactOutputSingleParams {
+            token_in: token0.address(),
+            token_out: token1.address(),
+            fee: 3000,
+            recipient: trader,
+            deadline: u64::MAX,
+            amount_out,
+            amount_in_maximum: amount_out,
+            sqrt_price_limit_x96: U256::zero(),
+        };
+
+        let amount_in = router.swap_exact_output_single(ExactOutputSingleParams {
+            amount_in_maximum: U256::from(2_000_000u128),
+            ..params.clone()
+        });
+        assert!(amount_in > amount_out);
+        assert_eq!(token1.balance_of(&trader), amount_out);
+        assert_eq!(token0.balance_of(&trader), U256::from(2_000_000u128) - amount_in);
+
+        env.advance_block_time(1_000);
+        let expired = ExactOutputSingleParams { amount_in_maximum: U256::MAX, deadline: 0, ..params.clone() };
+        assert_eq!(router.try_swap_exact_output_single(expired), Err(Error::Expired.into()));
+
+        // Amounts above i128::MAX cannot be passed to the DEX
+        let too_large = ExactOutputSingleParams {
+            amount_out: U256::from(u128::MAX),
+            amount_in_maximum: U256::MAX,
+            ..params.clone()
+        };
+        env.set_caller(trader);
+        assert_eq!(router.try_swap_exact_output_single(too_large), Err(Error::AmountOverflow.into()));
+
+        // The fee alone pushes the input above a 1:1 maximum
+        env.set_caller(trader);
+        assert!(router.try_swap_exact_output_single(params).is_err());
+    }
+
+    #[test]
+    fn test_exact_output_multi_hop_nests_hops() {
+        let (env, mut dex) = deploy_dex();
+        let mut router = Router::deploy(&env, RouterInitArgs { dex_address: dex.address() });
+        let [mut token0, mut token1, mut token2] = deploy_tokens(&env, ["TKA", "TKB", "TKC"]);
+        let owner = env.get_account(0);
+        for token in [&mut token0, &mut token1, &mut token2] {
+            token.approve(&dex.address(), &U256::MAX);
+        }
+        for (token_a, token_b) in [(&token0, &token1), (&token1, &token2)] {
+            create_pool(&mut dex, token_a.address(), token_b.address());
+            add_liquidity(&mut dex, owner, token_a.address(), token_b.address());
+        }
+
+        let trader = env.get_account(1);
+        let amount_out = U256::from(1_000_000u128);
+        let amount_in_maximum = U256::from(2_000_000u128);
+        token0.transfer(&trader, &amount_in_maximum);
+        env.set_caller(trader);
+        token0.approve(&router.address(), &amount_in_maximum);
+
+        // Buy token2 with token0 through the token1 pools
+        let params = ExactOutputParams {
+            path: vec![token2.address(), token1.address(), token0.address()],
+            fees: vec![3000, 3000],
+            recipient: trader,
+            deadline: u64::MAX,
+            amount_out,
+            amount_in_maximum: amount_out,
+        };
+        assert_eq!(
+            router.try_swap_exact_output_multi_hop(params.clone()),
+            Err(Error::SlippageExceeded.into())
+        );
+
+        let amount_in = router.swap_exact_output_multi_hop(ExactOutputParams { amount_in_maximum, ..params });
+        assert!(amount_in > amount_out);
+        assert_eq!(token2.balance_of(&trader), amount_out);
+        assert_eq!(token0.balance_of(&trader), amount_in_maximum - amount_in);
+        for token in [&token0, &token1, &token2] {
+            assert!(token.balance_of(&router.address()).is_zero());
+        }
+
+        // The intermediate token only moved between the two pools
+        let (reserve0, reserve1) = dex.get_pool_reserves(token0.address(), token1.address(), FEE);
+        let (reserve1b, _) = dex.get_pool_reserves(token1.address(), token2.address(), FEE);
+        assert_eq!(reserve1 + reserve1b, token1.balance_of(&dex.address()));
+        assert_eq!(reserve0, token0.balance_of(&dex.address()));
+    }
+
+    #[test]
+    fn test_swap_callback_only_from_dex() {
+        let (env, mut router, token0, token1) = setup();
+        let data = Router::callback_data(token0.address(), token1.address(), env.get_account(0));
+
+        assert_eq!(
+            router.try_swap_callback(I128::from_i128(1_000), I128::from_i128(-1_000), data),
+            Err(Error::Unauthorized.into())
+        );
+    }
 }
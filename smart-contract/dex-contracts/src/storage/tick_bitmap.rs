@@ -2,9 +2,10 @@ use odra::{
     casper_types::U256,
     prelude::*,
 };
+use crate::errors::Error;
 /// Stores a packed mapping of tick index to its initialized state
 /// Allows gas-efficient iteration over initialized ticks
-#[odra::module]
+#[odra::module(errors = Error)]
 pub struct TickBitmap {
     /// word_position -> bitmap (256 ticks per word)
     bitmap: Mapping<i32, U256>,
@@ -22,7 +23,9 @@ impl TickBitmap {
     /// * `tick` - The tick to flip
     /// * `tick_spacing` - The spacing between usable ticks
     pub fn flip_tick(&mut self, tick: i32, tick_spacing: i32) {
-        assert!(tick % tick_spacing == 0, "Tick not aligned to spacing");
+        if tick % tick_spacing != 0 {
+            self.env().revert(Error::TicksNotAligned);
+        }
 
         let (word_pos, bit_pos) = Self::position(tick / tick_spacing);
         let mask = U256::one() << bit_pos;
@@ -50,17 +53,53 @@ impl TickBitmap {
         tick_spacing: i32,
         lte: bool,
     ) -> (i32, bool) {
+        let compressed = Self::compress(tick, tick_spacing);
+        let word_pos = if lte {
+            Self::position(compressed).0
+        } else {
+            // Start from the word of the next tick, since the current tick state doesn't matter
+            Self::position(compressed + 1).0
+        };
+        let word = self.bitmap.get_or_default(&word_pos);
+
+        Self::next_initialized_tick_in_word(word, compressed, tick_spacing, lte)
+    }
+}
+
+impl TickBitmap {
+    /// Compresses a tick by its spacing, rounding towards negative infinity
+    ///
+    /// # Arguments
+    /// * `tick` - The tick to compress
+    /// * `tick_spacing` - The spacing between usable ticks
+    pub fn compress(tick: i32, tick_spacing: i32) -> i32 {
         let compressed = tick / tick_spacing;
         if tick < 0 && tick % tick_spacing != 0 {
             // Round towards negative infinity
-            let _compressed = compressed - 1;
+            compressed - 1
+        } else {
+            compressed
         }
+    }
 
+    /// Searches one bitmap word for the next initialized tick, see `next_initialized_tick_within_one_word`
+    ///
+    /// # Arguments
+    /// * `word` - The word holding `compressed` if `lte`, or `compressed + 1` otherwise
+    /// * `compressed` - The starting tick, compressed by `tick_spacing`
+    /// * `tick_spacing` - The spacing between usable ticks
+    /// * `lte` - Whether to search for the next initialized tick to the left (less than or equal to the starting tick)
+    pub fn next_initialized_tick_in_word(
+        word: U256,
+        compressed: i32,
+        tick_spacing: i32,
+        lte: bool,
+    ) -> (i32, bool) {
         if lte {
-            let (word_pos, bit_pos) = Self::position(compressed);
+            let (_, bit_pos) = Self::position(compressed);
             // All the 1s at or to the right of the current bit_pos
             let mask = (U256::one() << bit_pos) - U256::one() + (U256::one() << bit_pos);
-            let masked = self.bitmap.get_or_default(&word_pos) & mask;
+            let masked = word & mask;
 
             // If there are no initialized ticks to the right of or at the current tick, return rightmost in the word
             let initialized = !masked.is_zero();
@@ -74,11 +113,10 @@ impl TickBitmap {
 
             (next, initialized)
         } else {
-            // Start from the word of the next tick, since the current tick state doesn't matter
-            let (word_pos, bit_pos) = Self::position(compressed + 1);
+            let (_, bit_pos) = Self::position(compressed + 1);
             // All the 1s at or to the left of the bit_pos
             let mask = !((U256::one() << bit_pos) - U256::one());
-            let masked = self.bitmap.get_or_default(&word_pos) & mask;
+            let masked = word & mask;
 
             // If there are no initialized ticks to the left of the current tick, return leftmost in the word
             let initialized = !masked.is_zero();
@@ -93,9 +131,7 @@ impl TickBitmap {
             (next, initialized)
         }
     }
-}
 
-impl TickBitmap {
     /// Computes the position in the mapping where the initialized bit for a tick lives
     ///
     /// # Arguments
@@ -252,6 +288,30 @@ mod tests {
         bitmap.flip_tick(0, 1);
         let word = bitmap.get_word(word_pos);
         assert_eq!(word, U256::zero());
+
+        // Ticks off the spacing grid are rejected
+        assert_eq!(bitmap.try_flip_tick(30, 60), Err(Error::TicksNotAligned.into()));
+    }
+
+    #[test]
+    fn test_compress_rounds_towards_negative_infinity() {
+        assert_eq!(TickBitmap::compress(120, 60), 2);
+        assert_eq!(TickBitmap::compress(-120, 60), -2);
+        assert_eq!(TickBitmap::compress(-1, 60), -1);
+        assert_eq!(TickBitmap::compress(-61, 60), -2);
+    }
+
+    #[test]
+    fn test_next_initialized_tick_with_negative_ticks() {
+        let env = odra_test::env();
+        let mut bitmap = TickBitmap::deploy(&env, NoArgs);
+        bitmap.flip_tick(-120, 60);
+
+        // Searching left from just below -60 must not skip -120
+        assert_eq!(bitmap.next_initialized_tick_within_one_word(-61, 60, true), (-120, true));
+        assert_eq!(bitmap.next_initialized_tick_within_one_word(-121, 60, false), (-120, true));
+        // -120 is the lowest initialized tick, so searching below it stops at the word boundary
+        assert_eq!(bitmap.next_initialized_tick_within_one_word(-121, 60, true), (-256 * 60, false));
     }
 
     #[test]
@@ -0,0 +1,55 @@
+//! Deployments shared by the contract unit tests
+
+use odra::{
+    casper_types::{U128, U256},
+    host::{Deployer, HostEnv, NoArgs},
+    prelude::*,
+};
+use odra_modules::erc20::{Erc20, Erc20HostRef, Erc20InitArgs};
+use crate::{
+    constants::{FEE_TIER_MEDIUM, Q96},
+    unified_dex::{UnifiedDex, UnifiedDexHostRef},
+};
+
+/// Fee tier of the pools the fixtures create
+pub const FEE: u32 = FEE_TIER_MEDIUM;
+
+/// Liquidity `add_liquidity` provides
+pub const LIQUIDITY: u128 = 1_000_000_000_000;
+
+/// Fresh environment calling from account 0, with the DEX deployed
+pub fn deploy_dex() -> (HostEnv, UnifiedDexHostRef) {
+    let env = odra_test::env();
+    env.set_caller(env.get_account(0));
+    let dex = UnifiedDex::deploy(&env, NoArgs);
+    (env, dex)
+}
+
+/// Deploy an 18-decimal token per symbol, minted to the caller and sorted by address so that
+/// neighbouring tokens are in pool order
+pub fn deploy_tokens<const N: usize>(env: &HostEnv, symbols: [&str; N]) -> [Erc20HostRef; N] {
+    let mut tokens = symbols.map(|symbol| {
+        Erc20::deploy(
+            env,
+            Erc20InitArgs {
+                symbol: symbol.to_string(),
+                name: symbol.to_string(),
+                decimals: 18,
+                initial_supply: Some(U256::from(10u128.pow(30))),
+            },
+        )
+    });
+    tokens.sort_by_key(|token| token.address());
+    tokens
+}
+
+/// Create the pair's `FEE` pool and initialize it at price 1
+pub fn create_pool(dex: &mut UnifiedDexHostRef, token0: Address, token1: Address) {
+    dex.create_pool(token0, token1, FEE);
+    dex.initialize_pool(token0, token1, FEE, U256::from(Q96));
+}
+
+/// Add `LIQUIDITY` for `owner` on `-600..600` of the pair's `FEE` pool, paid by the caller
+pub fn add_liquidity(dex: &mut UnifiedDexHostRef, owner: Address, token0: Address, token1: Address) -> (U256, U256) {
+    dex.mint(token0, token1, FEE, owner, -600, 600, U128::from(LIQUIDITY), U256::zero(), U256::zero())
+}
@@ -1,15 +1,28 @@
+// Event constructors take one argument per field
+#![allow(clippy::too_many_arguments)]
+
 use odra::prelude::*;
 use odra::casper_types::{U256, U128};
 use crate::types::tick::I128;
 
 #[odra::event]
 pub struct Initialize {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub sqrt_price_x96: U256,
     pub tick: i32,
 }
 
 #[odra::event]
 pub struct Mint {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub sender: Address,
     pub owner: Address,
     pub tick_lower: i32,
@@ -21,6 +34,11 @@ pub struct Mint {
 
 #[odra::event]
 pub struct Collect {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub owner: Address,
     pub recipient: Address,
     pub tick_lower: i32,
@@ -31,6 +49,11 @@ pub struct Collect {
 
 #[odra::event]
 pub struct Burn {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub owner: Address,
     pub tick_lower: i32,
     pub tick_upper: i32,
@@ -41,6 +64,11 @@ pub struct Burn {
 
 #[odra::event]
 pub struct Swap {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub sender: Address,
     pub recipient: Address,
     pub amount0: I128,
@@ -52,6 +80,11 @@ pub struct Swap {
 
 #[odra::event]
 pub struct Flash {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub sender: Address,
     pub recipient: Address,
     pub amount0: U256,
@@ -62,12 +95,22 @@ pub struct Flash {
 
 #[odra::event]
 pub struct IncreaseObservationCardinalityNext {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub observation_cardinality_next_old: u32,
     pub observation_cardinality_next_new: u32,
 }
 
 #[odra::event]
 pub struct SetFeeProtocol {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub fee_protocol_0_old: u8,
     pub fee_protocol_1_old: u8,
     pub fee_protocol_0_new: u8,
@@ -76,6 +119,11 @@ pub struct SetFeeProtocol {
 
 #[odra::event]
 pub struct CollectProtocol {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub sender: Address,
     pub recipient: Address,
     pub amount0: U128,
@@ -87,6 +135,43 @@ pub struct PoolCreated {
     pub token0: Address,
     pub token1: Address,
     pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
     pub tick_spacing: i32,
     pub pool: Address,
-}
\ No newline at end of file
+}
+
+#[odra::event]
+pub struct PoolPauseSet {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub pool_id: [u8; 32],
+    pub sequence: u64,
+    pub sender: Address,
+    pub paused_actions_old: u8,
+    pub paused_actions_new: u8,
+}
+
+#[odra::event]
+pub struct GlobalPauseSet {
+    pub sender: Address,
+    pub paused_actions_old: u8,
+    pub paused_actions_new: u8,
+}
+
+#[odra::event]
+pub struct PriceFeedSet {
+    pub base: Address,
+    pub quote: Address,
+    pub fee: u32,
+    pub window: u32,
+    pub intermediate: Option<Address>,
+    pub intermediate_fee: u32,
+}
+
+#[odra::event]
+pub struct PriceFeedRemoved {
+    pub base: Address,
+    pub quote: Address,
+}
@@ -17,7 +17,7 @@ pub struct Slot0 {
     pub observation_cardinality: u32,
     /// The next observation cardinality to use
     pub observation_cardinality_next: u32,
-    /// The current protocol fee as a percentage of the swap fee (0-100)
+    /// The protocol fee as 1/N of the swap fee: token0 in the low 4 bits, token1 in the high 4 bits (0 = off)
     pub fee_protocol: u8,
     /// Whether the pool is currently locked
     pub unlocked: bool,
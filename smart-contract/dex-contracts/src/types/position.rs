@@ -1,7 +1,9 @@
 use odra::{
-    casper_types::{U128, U256},
+    casper_types::{bytesrepr::ToBytes, Digest, U128, U256},
     prelude::*,
 };
+use crate::errors::Error;
+use crate::math::{FullMath, LiquidityMath};
 
 /// Positions represent an owner address' liquidity between a lower and upper tick boundary
 #[odra::odra_type]
@@ -42,20 +44,11 @@ impl PositionKey {
     }
 
     /// Computes the hash of the position key (for use as mapping key)
+    ///
+    /// blake2b-256 over the bytesrepr encoding of `(owner, tick_lower, tick_upper)`, the
+    /// same digest as `ContractEnv::hash`, so keys can be derived off-chain as well
     pub fn compute_key(&self) -> [u8; 32] {
-        use odra::casper_types::bytesrepr::ToBytes;
-
-        let mut key_bytes = Vec::new();
-        key_bytes.extend_from_slice(&self.owner.to_bytes().unwrap());
-        key_bytes.extend_from_slice(&self.tick_lower.to_le_bytes());
-        key_bytes.extend_from_slice(&self.tick_upper.to_le_bytes());
-
-        // Simple hash - in production use a proper hash function like blake2b
-        let mut hash = [0u8; 32];
-        for (i, byte) in key_bytes.iter().enumerate() {
-            hash[i % 32] ^= byte;
-        }
-        hash
+        Digest::hash(self.to_bytes().unwrap()).value()
     }
 }
 
@@ -71,37 +64,35 @@ impl Position {
         liquidity_delta: i128,
         fee_growth_inside_0_x128: U256,
         fee_growth_inside_1_x128: U256,
-    ) {
-        // Calculate fees owed before updating position
-        if liquidity_delta == 0 {
-            // If no liquidity change, just collect fees
-            assert!(!self.liquidity.is_zero(), "Cannot update position with 0 liquidity");
-        } else {
-            self.liquidity = if liquidity_delta < 0 {
-                self.liquidity - liquidity_delta.unsigned_abs()
-            } else {
-                self.liquidity + liquidity_delta.unsigned_abs()
-            };
+    ) -> Result<(), Error> {
+        if liquidity_delta == 0 && self.liquidity.is_zero() {
+            // A poke only collects fees, which an empty position has none of
+            return Err(Error::ZeroLiquidity);
         }
 
-        // Calculate and accumulate fees
+        // Fees accrued since the last checkpoint belong to the liquidity held before this update
         let tokens_owed_0 = Self::calculate_fees_owed(
             self.liquidity,
             fee_growth_inside_0_x128,
             self.fee_growth_inside_0_last_x128,
-        );
+        )?;
         let tokens_owed_1 = Self::calculate_fees_owed(
             self.liquidity,
             fee_growth_inside_1_x128,
             self.fee_growth_inside_1_last_x128,
-        );
+        )?;
+
+        if liquidity_delta != 0 {
+            self.liquidity = LiquidityMath::add_delta(self.liquidity, liquidity_delta)?;
+        }
 
-        self.tokens_owed_0 += tokens_owed_0;
-        self.tokens_owed_1 += tokens_owed_1;
+        self.tokens_owed_0 = self.tokens_owed_0.checked_add(tokens_owed_0).ok_or(Error::FeesOverflow)?;
+        self.tokens_owed_1 = self.tokens_owed_1.checked_add(tokens_owed_1).ok_or(Error::FeesOverflow)?;
 
         // Update fee growth checkpoints
         self.fee_growth_inside_0_last_x128 = fee_growth_inside_0_x128;
         self.fee_growth_inside_1_last_x128 = fee_growth_inside_1_x128;
+        Ok(())
     }
 
     /// Calculates fees owed to the position
@@ -109,15 +100,18 @@ impl Position {
         liquidity: U128,
         fee_growth_inside: U256,
         fee_growth_inside_last: U256,
-    ) -> U128 {
-        let fee_growth_delta = fee_growth_inside - fee_growth_inside_last;
+    ) -> Result<U128, Error> {
+        // Fee growth accumulators wrap, so the delta is taken modulo 2^256
+        let fee_growth_delta = fee_growth_inside.overflowing_sub(fee_growth_inside_last).0;
         let liquidity_u256 = U256::from(liquidity.as_u128());
-        let fees = (liquidity_u256 * fee_growth_delta) >> 128;
+        let fees = FullMath::mul_div(liquidity_u256, fee_growth_delta, U256::one() << 128)?;
 
         // Ensure fees fit in U128
         let u128_max_as_u256 = (U256::one() << 128) - U256::one();
-        assert!(fees <= u128_max_as_u256, "Fees overflow");
-        fees.as_u128().into()
+        if fees > u128_max_as_u256 {
+            return Err(Error::FeesOverflow);
+        }
+        Ok(fees.as_u128().into())
     }
 
     /// Gets the current fees owed without updating the position
@@ -125,19 +119,22 @@ impl Position {
         &self,
         fee_growth_inside_0_x128: U256,
         fee_growth_inside_1_x128: U256,
-    ) -> (U128, U128) {
-        let tokens_owed_0 = self.tokens_owed_0 + Self::calculate_fees_owed(
+    ) -> Result<(U128, U128), Error> {
+        let fees_0 = Self::calculate_fees_owed(
             self.liquidity,
             fee_growth_inside_0_x128,
             self.fee_growth_inside_0_last_x128,
-        );
-        let tokens_owed_1 = self.tokens_owed_1 + Self::calculate_fees_owed(
+        )?;
+        let fees_1 = Self::calculate_fees_owed(
             self.liquidity,
             fee_growth_inside_1_x128,
             self.fee_growth_inside_1_last_x128,
-        );
+        )?;
 
-        (tokens_owed_0, tokens_owed_1)
+        Ok((
+            self.tokens_owed_0.checked_add(fees_0).ok_or(Error::FeesOverflow)?,
+            self.tokens_owed_1.checked_add(fees_1).ok_or(Error::FeesOverflow)?,
+        ))
     }
 }
 
@@ -156,11 +153,27 @@ mod tests {
         assert_eq!(key.tick_upper, 100);
     }
 
+    #[test]
+    fn test_position_key_is_collision_resistant() {
+        let owner = Address::from(AccountHash::new([1u8; 32]));
+        let other = Address::from(AccountHash::new([2u8; 32]));
+
+        let key = PositionKey::new(owner, -100, 100).compute_key();
+        assert_eq!(key, PositionKey::new(owner, -100, 100).compute_key());
+        assert_ne!(key, PositionKey::new(other, -100, 100).compute_key());
+        // Swapping or re-splitting the tick bytes no longer yields the same key
+        assert_ne!(key, PositionKey::new(owner, 100, -100).compute_key());
+        assert_ne!(
+            PositionKey::new(owner, 0x0100, 0).compute_key(),
+            PositionKey::new(owner, 0, 0x0100).compute_key()
+        );
+    }
+
     #[test]
     fn test_position_update_add_liquidity() {
         let mut position = Position::default();
 
-        position.update(1000, U256::zero(), U256::zero());
+        position.update(1000, U256::zero(), U256::zero()).unwrap();
 
         assert_eq!(position.liquidity, 1000u128.into());
     }
@@ -172,7 +185,7 @@ mod tests {
             ..Default::default()
         };
 
-        position.update(-500, U256::zero(), U256::zero());
+        position.update(-500, U256::zero(), U256::zero()).unwrap();
 
         assert_eq!(position.liquidity, 500u128.into());
     }
@@ -188,9 +201,34 @@ mod tests {
 
         // Simulate fee growth
         let fee_growth = U256::one() << 128; // 1.0 in Q128
-        position.update(0, fee_growth, fee_growth);
+        position.update(0, fee_growth, fee_growth).unwrap();
 
         assert_eq!(position.tokens_owed_0, 1000u128.into());
         assert_eq!(position.tokens_owed_1, 1000u128.into());
     }
+
+    #[test]
+    fn test_position_fees_accrue_on_liquidity_before_update() {
+        let mut position = Position {
+            liquidity: 1000u128.into(),
+            ..Default::default()
+        };
+
+        // Fees earned before the deposit belong to the existing 1000 units only
+        let fee_growth = U256::one() << 128;
+        position.update(1000, fee_growth, fee_growth).unwrap();
+
+        assert_eq!(position.liquidity, 2000u128.into());
+        assert_eq!(position.tokens_owed_0, 1000u128.into());
+        assert_eq!(position.tokens_owed_1, 1000u128.into());
+    }
+
+    #[test]
+    fn test_position_update_rejects_empty_poke_and_overdraw() {
+        let mut position = Position::default();
+        assert_eq!(position.update(0, U256::zero(), U256::zero()), Err(Error::ZeroLiquidity));
+
+        position.update(1000, U256::zero(), U256::zero()).unwrap();
+        assert_eq!(position.update(-1001, U256::zero(), U256::zero()), Err(Error::LiquidityUnderflow));
+    }
 }
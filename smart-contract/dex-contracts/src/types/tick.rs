@@ -1,15 +1,76 @@
 use odra::{
-    casper_types::{U256, U128},
+    casper_types::{
+        bytesrepr::{self, FromBytes, ToBytes},
+        CLType, CLTyped, U256, U128,
+    },
+    prelude::*,
 };
+use crate::constants::{MAX_TICK, MIN_TICK};
+use crate::errors::Error;
+use crate::math::LiquidityMath;
 
 /// Custom I128 wrapper since i128 is not supported in Casper serialization
-#[odra::odra_type]
-#[derive(Default)]
+///
+/// Serialized as the `(U128, bool)` tuple rather than through `#[odra::odra_type]`, whose
+/// `CLType::Any` the event standard rejects, so that events can carry signed amounts.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
 pub struct I128 {
     pub abs: U128,
     pub is_positive: bool,
 }
 
+impl ToBytes for I128 {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        (self.abs, self.is_positive).to_bytes()
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.abs.serialized_length() + self.is_positive.serialized_length()
+    }
+}
+
+impl FromBytes for I128 {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let ((abs, is_positive), rem) = <(U128, bool)>::from_bytes(bytes)?;
+        Ok((Self { abs, is_positive }, rem))
+    }
+}
+
+impl CLTyped for I128 {
+    fn cl_type() -> CLType {
+        <(U128, bool)>::cl_type()
+    }
+}
+
+impl odra::contract_def::HasEvents for I128 {
+    fn events() -> Vec<odra::contract_def::Event> {
+        vec![]
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl odra::schema::SchemaCustomTypes for I128 {
+    fn schema_types() -> Vec<Option<odra::schema::casper_contract_schema::CustomType>> {
+        vec![Some(odra::schema::custom_struct(
+            "I128",
+            vec![
+                odra::schema::struct_member::<U128>("abs"),
+                odra::schema::struct_member::<bool>("is_positive"),
+            ],
+        ))]
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl odra::schema::NamedCLTyped for I128 {
+    fn ty() -> odra::schema::casper_contract_schema::NamedCLType {
+        odra::schema::casper_contract_schema::NamedCLType::Custom(String::from("I128"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl odra::schema::SchemaCustomElement for I128 {}
+
 impl I128 {
     pub fn from_i128(val: i128) -> Self {
         Self {
@@ -60,6 +121,21 @@ pub struct Tick {
 }
 
 impl Tick {
+    /// Derives max liquidity per tick from given tick spacing
+    /// Executed within the pool constructor
+    ///
+    /// # Arguments
+    /// * `tick_spacing` - The amount of required tick separation, realized in multiples of `tick_spacing`
+    ///
+    /// # Returns
+    /// The max liquidity per tick
+    pub fn tick_spacing_to_max_liquidity_per_tick(tick_spacing: i32) -> U128 {
+        let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+        let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+        let num_ticks = ((max_tick - min_tick) / tick_spacing) as u32 + 1;
+        U128::MAX / U128::from(num_ticks)
+    }
+
     /// Updates a tick and returns true if the tick was flipped from initialized to uninitialized, or vice versa
     ///
     /// # Arguments
@@ -88,15 +164,13 @@ impl Tick {
         time: u32,
         upper: bool,
         max_liquidity: U128,
-    ) -> bool {
+    ) -> Result<bool, Error> {
         let liquidity_gross_before = self.liquidity_gross;
-        let liquidity_gross_after = if liquidity_delta < 0 {
-            self.liquidity_gross - liquidity_delta.unsigned_abs()
-        } else {
-            self.liquidity_gross + liquidity_delta.unsigned_abs()
-        };
+        let liquidity_gross_after = LiquidityMath::add_delta(liquidity_gross_before, liquidity_delta)?;
 
-        assert!(liquidity_gross_after <= max_liquidity, "Liquidity > max");
+        if liquidity_gross_after > max_liquidity {
+            return Err(Error::LiquidityExceedsMax);
+        }
 
         let flipped = liquidity_gross_after.is_zero() != liquidity_gross_before.is_zero();
 
@@ -123,7 +197,60 @@ impl Tick {
         };
         self.liquidity_net = I128::from_i128(new_liquidity_net);
 
-        flipped
+        Ok(flipped)
+    }
+
+    /// Retrieves fee growth data for the range between two ticks
+    ///
+    /// # Arguments
+    /// * `lower` - The lower tick boundary of the position
+    /// * `upper` - The upper tick boundary of the position
+    /// * `tick_lower` - The lower tick index
+    /// * `tick_upper` - The upper tick index
+    /// * `tick_current` - The current tick
+    /// * `fee_growth_global_0_x128` - The all-time global fee growth, per unit of liquidity, in token0
+    /// * `fee_growth_global_1_x128` - The all-time global fee growth, per unit of liquidity, in token1
+    ///
+    /// # Returns
+    /// The all-time fee growth in token0 and token1, per unit of liquidity, inside the position's tick boundaries
+    pub fn get_fee_growth_inside(
+        lower: &Tick,
+        upper: &Tick,
+        tick_lower: i32,
+        tick_upper: i32,
+        tick_current: i32,
+        fee_growth_global_0_x128: U256,
+        fee_growth_global_1_x128: U256,
+    ) -> (U256, U256) {
+        // Calculate fee growth below
+        let (fee_growth_below_0_x128, fee_growth_below_1_x128) = if tick_current >= tick_lower {
+            (lower.fee_growth_outside_0_x128, lower.fee_growth_outside_1_x128)
+        } else {
+            (
+                fee_growth_global_0_x128.overflowing_sub(lower.fee_growth_outside_0_x128).0,
+                fee_growth_global_1_x128.overflowing_sub(lower.fee_growth_outside_1_x128).0,
+            )
+        };
+
+        // Calculate fee growth above
+        let (fee_growth_above_0_x128, fee_growth_above_1_x128) = if tick_current < tick_upper {
+            (upper.fee_growth_outside_0_x128, upper.fee_growth_outside_1_x128)
+        } else {
+            (
+                fee_growth_global_0_x128.overflowing_sub(upper.fee_growth_outside_0_x128).0,
+                fee_growth_global_1_x128.overflowing_sub(upper.fee_growth_outside_1_x128).0,
+            )
+        };
+
+        // Underflow is expected here, the accumulators only have relative meaning
+        (
+            fee_growth_global_0_x128
+                .overflowing_sub(fee_growth_below_0_x128).0
+                .overflowing_sub(fee_growth_above_0_x128).0,
+            fee_growth_global_1_x128
+                .overflowing_sub(fee_growth_below_1_x128).0
+                .overflowing_sub(fee_growth_above_1_x128).0,
+        )
     }
 
     /// Clears tick data
@@ -1,18 +1,32 @@
 use odra::{
-    casper_types::{U256, U128},
+    casper_types::{
+        bytesrepr::{Bytes, ToBytes},
+        Digest, U256, U128,
+    },
     prelude::*,
     ContractRef,
 };
-use odra_modules::{access::Ownable, erc20::Erc20ContractRef};
+use odra_modules::{access::Role, erc20::Erc20ContractRef};
 use crate::{
+    access::{Roles, FEE_TIER_ADMIN_ROLE, PAUSER_ROLE, PROTOCOL_FEE_COLLECTOR_ROLE},
+    callbacks::{
+        UnifiedDexFlashCallbackContractRef, UnifiedDexMintCallbackContractRef,
+        UnifiedDexSwapCallbackContractRef,
+    },
     constants::*,
-    math::TickMath,
+    errors::Error,
+    guard::EntryGuard,
+    math::{FullMath, LiquidityMath, OracleLibrary, SwapMath, TickMath},
+    reserves::Reserves,
+    storage::TickBitmap,
     types::{
-        tick::I128,
-        events::{Burn, Collect, Initialize, Mint, PoolCreated},
-        pool_info::{Observation, Slot0},
-        position::Position,
-        tick::Tick,
+        events::{
+            Burn, Collect, CollectProtocol, Flash, GlobalPauseSet, IncreaseObservationCardinalityNext,
+            Initialize, Mint, PoolCreated, PoolPauseSet, SetFeeProtocol, Swap,
+        },
+        pool_info::{Observation, Slot0, StepComputations, SwapState},
+        position::{Position, PositionKey},
+        tick::{Tick, I128},
     },
 };
 
@@ -51,19 +65,44 @@ impl Default for PoolData {
 }
 
 /// Unified DEX contract managing all pools
-#[odra::module(events = [PoolCreated, Initialize, Mint, Burn, Collect])]
+///
+/// Token pairs may be passed in either order. Prices, ticks, amounts and swap directions in
+/// arguments, return values and callbacks then follow the caller's order: swapping the tokens
+/// inverts prices, negates ticks (a `[lower, upper)` range becomes `[-upper, -lower)`) and swaps
+/// per-token amounts. Structs mirroring pool storage (`PoolData`, `Tick`, `Position`,
+/// `Observation`, `PopulatedTick`), the raw tick views and events always use pool order, except
+/// for the `Position` returned by `get_position_with_fees`.
+#[odra::module(
+    events = [
+        PoolCreated, Initialize, Mint, Burn, Collect, Swap, SetFeeProtocol, CollectProtocol, Flash,
+        IncreaseObservationCardinalityNext, PoolPauseSet, GlobalPauseSet
+    ],
+    errors = Error
+)]
 pub struct UnifiedDex {
-    ownable: SubModule<Ownable>,
+    roles: SubModule<Roles>,
     
     // Pool management
     pools: Mapping<(Address, Address, u32), PoolData>,
     fee_amount_tick_spacing: Mapping<u32, i32>,
+    fee_amounts: List<u32>,  // Enabled fee tiers, in the order they were enabled
+    all_pools: List<(Address, Address, u32)>,  // Pool keys, in creation order
+    pool_keys: Mapping<[u8; 32], (Address, Address, u32)>,  // pool_id -> pool key
+    pool_creators: Mapping<[u8; 32], Address>,  // pool_id -> account that created the pool
+    initialization_restricted: Var<bool>,  // Only the owner may call create_pool, and pool creators (or the owner) initialize_pool
+
+    // Emergency controls
+    guard: SubModule<EntryGuard>,
+
+    // Tokens held for each pool, which payments are checked against
+    reserves: SubModule<Reserves>,
     
     // Pool-specific storage (keyed by pool_id)
     ticks: Mapping<([u8; 32], i32), Tick>,
     positions: Mapping<([u8; 32], [u8; 32]), Position>,
     tick_bitmaps: Mapping<([u8; 32], i32), U256>,  // (pool_id, word_pos) -> bitmap (i32 for ToBytes support)
     observations: Mapping<([u8; 32], u32), Observation>,
+    event_sequences: Mapping<[u8; 32], u64>,  // pool_id -> sequence number of the next pool event
 }
 
 #[odra::module]
@@ -71,22 +110,44 @@ impl UnifiedDex {
     #[odra(init)]
     pub fn init(&mut self) {
         let caller = self.env().caller();
-        self.ownable.init(caller);
+        self.roles.init(caller);
         
         // Initialize default fee tiers
         self.fee_amount_tick_spacing.set(&500, 10);    // 0.05%
         self.fee_amount_tick_spacing.set(&3000, 60);   // 0.3%
         self.fee_amount_tick_spacing.set(&10000, 200); // 1%
+        for fee in [500, 3000, 10000] {
+            self.fee_amounts.push(fee);
+        }
     }
 
     /// Create a new pool
+    ///
+    /// While initialization is restricted, only the owner may create a pool without pricing it.
+    /// Anyone else lists through `create_and_initialize_pool_if_necessary`, so no account can
+    /// claim a pool as its creator and hold back or set its starting price.
     pub fn create_pool(
         &mut self,
         token_a: Address,
         token_b: Address,
         fee: u32,
     ) -> [u8; 32] {
-        assert!(token_a != token_b, "Identical addresses");
+        if self.initialization_restricted.get_or_default() {
+            self.roles.assert_owner();
+        }
+        self._create_pool(token_a, token_b, fee)
+    }
+
+    /// Create a pool and record the caller as its creator
+    fn _create_pool(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+    ) -> [u8; 32] {
+        if token_a == token_b {
+            self.env().revert(Error::IdenticalTokens);
+        }
         let (token0, token1) = if token_a < token_b {
             (token_a, token_b)
         } else {
@@ -94,17 +155,20 @@ impl UnifiedDex {
         };
 
         let tick_spacing = self.fee_amount_tick_spacing.get(&fee).unwrap_or(0);
-        assert!(tick_spacing != 0, "Fee not enabled");
+        if tick_spacing == 0 {
+            self.env().revert(Error::FeeNotEnabled);
+        }
 
         let pool_key = (token0, token1, fee);
-        assert!(self.pools.get(&pool_key).is_none(), "Pool already exists");
+        if self.pools.get(&pool_key).is_some() {
+            self.env().revert(Error::PoolAlreadyExists);
+        }
 
         // Calculate pool ID
         let pool_id = Self::compute_pool_id(token0, token1, fee);
 
         // Create pool data
-        let tick_spacing_u128 = U128::from(tick_spacing.unsigned_abs());
-        let max_liquidity = U128::MAX / tick_spacing_u128;
+        let max_liquidity = Tick::tick_spacing_to_max_liquidity_per_tick(tick_spacing);
 
         let pool_data = PoolData {
             token0,
@@ -120,11 +184,16 @@ impl UnifiedDex {
         };
 
         self.pools.set(&pool_key, pool_data);
+        self.all_pools.push(pool_key);
+        self.pool_keys.set(&pool_id, pool_key);
+        self.pool_creators.set(&pool_id, self.env().caller());
 
         self.env().emit_event(PoolCreated {
             token0,
             token1,
             fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
             tick_spacing,
             pool: self.env().self_address(), // This contract manages all pools
         });
@@ -132,7 +201,53 @@ impl UnifiedDex {
         pool_id
     }
 
+    /// Create a pool and set its starting price in one call, so the listing price cannot be front-run
+    ///
+    /// Creates the pool if it does not exist and initializes it if it has no price yet. A pool that
+    /// is already initialized is left untouched as long as it sits at the tick of `sqrt_price_x96`;
+    /// any other price reverts, so the caller never ends up listed at a price they did not set.
+    ///
+    /// # Arguments
+    /// * `token_a` - First token address
+    /// * `token_b` - Second token address
+    /// * `fee` - Fee tier
+    /// * `sqrt_price_x96` - Square root of the price of `token_a` in `token_b`, as a Q64.96
+    ///
+    /// # Returns
+    /// The pool id
+    pub fn create_and_initialize_pool_if_necessary(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+        sqrt_price_x96: U256,
+    ) -> [u8; 32] {
+        if self.get_pool(token_a, token_b, fee).is_none() {
+            self._create_pool(token_a, token_b, fee);
+        }
+        let pool_data = self.get_pool(token_a, token_b, fee).unwrap();
+
+        if pool_data.slot0.sqrt_price_x96.is_zero() {
+            self.initialize_pool(token_a, token_b, fee, sqrt_price_x96);
+        } else {
+            let (_, _, flipped) = Self::_sort_tokens(token_a, token_b);
+            let sqrt_price_x96 = if flipped && !sqrt_price_x96.is_zero() {
+                Self::_invert_sqrt_price(sqrt_price_x96).unwrap_or_revert(self)
+            } else {
+                sqrt_price_x96
+            };
+            if pool_data.slot0.tick != TickMath::get_tick_at_sqrt_ratio(sqrt_price_x96).unwrap_or_revert(self) {
+                self.env().revert(Error::PoolPriceMismatch);
+            }
+        }
+
+        Self::compute_pool_id(pool_data.token0, pool_data.token1, fee)
+    }
+
     /// Initialize a pool with starting price
+    ///
+    /// `sqrt_price_x96` is the square root of the price of the first token in the second, as a Q64.96.
+    /// While initialization is restricted, only the pool creator or the owner may call this.
     pub fn initialize_pool(
         &mut self,
         token0: Address,
@@ -140,12 +255,27 @@ impl UnifiedDex {
         fee: u32,
         sqrt_price_x96: U256,
     ) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let sqrt_price_x96 = if flipped && !sqrt_price_x96.is_zero() {
+            Self::_invert_sqrt_price(sqrt_price_x96).unwrap_or_revert(self)
+        } else {
+            sqrt_price_x96
+        };
         let pool_key = (token0, token1, fee);
-        let mut pool_data = self.pools.get(&pool_key).expect("Pool does not exist");
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
         
-        assert!(pool_data.slot0.sqrt_price_x96.is_zero(), "Already initialized");
+        if !pool_data.slot0.sqrt_price_x96.is_zero() {
+            self.env().revert(Error::PoolAlreadyInitialized);
+        }
+        if self.initialization_restricted.get_or_default() {
+            let caller = self.env().caller();
+            let creator = self.pool_creators.get(&Self::compute_pool_id(token0, token1, fee));
+            if !(creator == Some(caller) || self.roles.get_owner() == caller) {
+                self.env().revert(Error::NotPoolCreator);
+            }
+        }
 
-        let tick = TickMath::get_tick_at_sqrt_ratio(sqrt_price_x96);
+        let tick = TickMath::get_tick_at_sqrt_ratio(sqrt_price_x96).unwrap_or_revert(self);
 
         pool_data.slot0.sqrt_price_x96 = sqrt_price_x96;
         pool_data.slot0.tick = tick;
@@ -157,7 +287,7 @@ impl UnifiedDex {
         // Initialize first observation
         let pool_id = Self::compute_pool_id(token0, token1, fee);
         let observation = Observation {
-            block_timestamp: self.env().get_block_time() as u32,
+            block_timestamp: self._block_timestamp(),
             tick_cumulative: 0,
             seconds_per_liquidity_cumulative_x128: U256::zero(),
             initialized: true,
@@ -165,12 +295,17 @@ impl UnifiedDex {
         self.observations.set(&(pool_id, 0), observation);
 
         self.env().emit_event(Initialize {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
             sqrt_price_x96,
             tick,
         });
     }
 
-    /// Add liquidity to a pool
+    /// Add liquidity to a pool, pulling the owed amounts from the caller with `transfer_from`
     pub fn mint(
         &mut self,
         token0: Address,
@@ -183,17 +318,91 @@ impl UnifiedDex {
         amount0_min: U256,
         amount1_min: U256,
     ) -> (U256, U256) {
-        assert!(!amount.is_zero(), "Amount cannot be zero");
+        self._mint(
+            token0,
+            token1,
+            fee,
+            recipient,
+            tick_lower,
+            tick_upper,
+            amount,
+            (amount0_min, amount1_min),
+            None,
+        )
+    }
+
+    /// Add liquidity to a pool, letting the caller pay the owed amounts from a callback
+    ///
+    /// The caller must be a contract implementing `UnifiedDexMintCallback`. Its
+    /// `mint_callback` must transfer the owed amounts to the DEX before returning.
+    ///
+    /// # Arguments
+    /// * `data` - Opaque data passed through to the callback
+    #[allow(clippy::too_many_arguments)]
+    pub fn mint_with_callback(
+        &mut self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        recipient: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: U128,
+        data: Bytes,
+    ) -> (U256, U256) {
+        self._mint(
+            token0,
+            token1,
+            fee,
+            recipient,
+            tick_lower,
+            tick_upper,
+            amount,
+            (U256::zero(), U256::zero()),
+            Some(data),
+        )
+    }
+
+    /// Add liquidity to a pool and collect the owed amounts, either with
+    /// `transfer_from` (`data` is `None`) or through the caller's mint callback
+    #[allow(clippy::too_many_arguments)]
+    fn _mint(
+        &mut self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        recipient: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: U128,
+        amounts_min: (U256, U256),
+        data: Option<Bytes>,
+    ) -> (U256, U256) {
+        if amount.is_zero() {
+            self.env().revert(Error::ZeroAmount);
+        }
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        self.guard.assert_not_paused(Self::compute_pool_id(token0, token1, fee), PAUSE_MINT);
+        let (tick_lower, tick_upper) = Self::_order_ticks(tick_lower, tick_upper, flipped);
 
         let pool_key = (token0, token1, fee);
-        let pool_data = self.pools.get(&pool_key).expect("Pool does not exist");
+        self._lock(pool_key);
+        let pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
 
         // Check pool is initialized
-        assert!(!pool_data.slot0.sqrt_price_x96.is_zero(), "Pool not initialized");
+        if pool_data.slot0.sqrt_price_x96.is_zero() {
+            self.env().revert(Error::PoolNotInitialized);
+        }
 
-        assert!(tick_lower < tick_upper, "Invalid tick range");
-        assert!(tick_lower >= MIN_TICK && tick_upper <= MAX_TICK, "Tick out of bounds");
-        assert!(tick_lower % pool_data.tick_spacing == 0 && tick_upper % pool_data.tick_spacing == 0, "Ticks not aligned");
+        if tick_lower >= tick_upper {
+            self.env().revert(Error::InvalidTickRange);
+        }
+        if tick_lower < MIN_TICK || tick_upper > MAX_TICK {
+            self.env().revert(Error::TickOutOfBounds);
+        }
+        if tick_lower % pool_data.tick_spacing != 0 || tick_upper % pool_data.tick_spacing != 0 {
+            self.env().revert(Error::TicksNotAligned);
+        }
 
         let pool_id = Self::compute_pool_id(token0, token1, fee);
         let (amount0, amount1) = self._modify_position(
@@ -202,48 +411,54 @@ impl UnifiedDex {
             recipient,
             tick_lower,
             tick_upper,
-            amount.as_u128() as i64,
+            i128::try_from(amount.as_u128()).ok().unwrap_or_revert_with(self, Error::LiquidityOverflow),
         );
 
         // Slippage protection
-        assert!(amount0 >= amount0_min, "Amount0 less than minimum");
-        assert!(amount1 >= amount1_min, "Amount1 less than minimum");
+        let (amount0_min, amount1_min) = Self::_order(amounts_min, flipped);
+        if amount0 < amount0_min || amount1 < amount1_min {
+            self.env().revert(Error::SlippageExceeded);
+        }
 
-        // Transfer tokens with fee-on-transfer protection
+        // Collect the owed amounts with fee-on-transfer protection
         let sender = self.env().caller();
         let dex_address = self.env().self_address();
+        let unaccounted0_before = self.reserves.unaccounted(token0);
+        let unaccounted1_before = self.reserves.unaccounted(token1);
 
-        if !amount0.is_zero() {
-            let mut token0_contract = Erc20ContractRef::new(self.env(), token0);
-            let balance_before = token0_contract.balance_of(&dex_address);
-            token0_contract.transfer_from(&sender, &dex_address, &amount0);
-            let balance_after = token0_contract.balance_of(&dex_address);
-            let received = balance_after - balance_before;
-            assert!(received >= amount0, "Fee-on-transfer tokens not supported");
+        match data {
+            Some(data) => {
+                if !sender.is_contract() {
+                    self.env().revert(Error::CallerNotContract);
+                }
+                let (amount_a_owed, amount_b_owed) = Self::_order((amount0, amount1), flipped);
+                UnifiedDexMintCallbackContractRef::new(self.env(), sender)
+                    .mint_callback(amount_a_owed, amount_b_owed, data);
+            }
+            None => {
+                if !amount0.is_zero() {
+                    Erc20ContractRef::new(self.env(), token0).transfer_from(&sender, &dex_address, &amount0);
+                }
+                if !amount1.is_zero() {
+                    Erc20ContractRef::new(self.env(), token1).transfer_from(&sender, &dex_address, &amount1);
+                }
+            }
         }
 
-        if !amount1.is_zero() {
-            let mut token1_contract = Erc20ContractRef::new(self.env(), token1);
-            let balance_before = token1_contract.balance_of(&dex_address);
-            token1_contract.transfer_from(&sender, &dex_address, &amount1);
-            let balance_after = token1_contract.balance_of(&dex_address);
-            let received = balance_after - balance_before;
-            assert!(received >= amount1, "Fee-on-transfer tokens not supported");
-        }
-
-        // Update TWAP oracle observation after liquidity change
-        let mut pool_data_updated = self.pools.get(&pool_key).expect("Pool does not exist");
-        let current_tick = pool_data_updated.slot0.tick;
-        let current_liquidity = pool_data_updated.liquidity;
-        pool_data_updated.slot0 = self._write_observation(
-            pool_id,
-            pool_data_updated.slot0,
-            current_tick,
-            current_liquidity,
-        );
-        self.pools.set(&pool_key, pool_data_updated);
+        let received0 = self.reserves.unaccounted(token0).saturating_sub(unaccounted0_before);
+        let received1 = self.reserves.unaccounted(token1).saturating_sub(unaccounted1_before);
+        if received0 < amount0 || received1 < amount1 {
+            self.env().revert(Error::InsufficientPayment);
+        }
+        self.reserves.credit(pool_id, token0, received0);
+        self.reserves.credit(pool_id, token1, received1);
 
         self.env().emit_event(Mint {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
             sender,
             owner: recipient,
             tick_lower,
@@ -253,7 +468,9 @@ impl UnifiedDex {
             amount1,
         });
 
-        (amount0, amount1)
+        self._unlock(pool_key);
+
+        Self::_order((amount0, amount1), flipped)
     }
 
     /// Remove liquidity from a pool
@@ -266,8 +483,10 @@ impl UnifiedDex {
         tick_upper: i32,
         amount: U128,
     ) -> (U256, U256) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let (tick_lower, tick_upper) = Self::_order_ticks(tick_lower, tick_upper, flipped);
         let pool_key = (token0, token1, fee);
-        assert!(self.pools.get(&pool_key).is_some(), "Pool does not exist");
+        self._lock(pool_key);
 
         let caller = self.env().caller();
         let pool_id = Self::compute_pool_id(token0, token1, fee);
@@ -278,29 +497,22 @@ impl UnifiedDex {
             caller,
             tick_lower,
             tick_upper,
-            -(amount.as_u128() as i64),
+            -i128::try_from(amount.as_u128()).ok().unwrap_or_revert_with(self, Error::LiquidityOverflow),
         );
 
         // Update tokens owed
         let position_key = Self::compute_position_key(caller, tick_lower, tick_upper);
         let mut position = self.positions.get(&(pool_id, position_key)).unwrap_or_default();
-        position.tokens_owed_0 = position.tokens_owed_0 + amount0.as_u128();
-        position.tokens_owed_1 = position.tokens_owed_1 + amount1.as_u128();
+        position.tokens_owed_0 = self._add_owed(position.tokens_owed_0, amount0);
+        position.tokens_owed_1 = self._add_owed(position.tokens_owed_1, amount1);
         self.positions.set(&(pool_id, position_key), position);
 
-        // Update TWAP oracle observation after liquidity change
-        let mut pool_data_updated = self.pools.get(&pool_key).expect("Pool does not exist");
-        let current_tick = pool_data_updated.slot0.tick;
-        let current_liquidity = pool_data_updated.liquidity;
-        pool_data_updated.slot0 = self._write_observation(
-            pool_id,
-            pool_data_updated.slot0,
-            current_tick,
-            current_liquidity,
-        );
-        self.pools.set(&pool_key, pool_data_updated);
-
         self.env().emit_event(Burn {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
             owner: caller,
             tick_lower,
             tick_upper,
@@ -309,7 +521,9 @@ impl UnifiedDex {
             amount1,
         });
 
-        (amount0, amount1)
+        self._unlock(pool_key);
+
+        Self::_order((amount0, amount1), flipped)
     }
 
     /// Collect tokens owed
@@ -324,13 +538,23 @@ impl UnifiedDex {
         amount0_requested: U128,
         amount1_requested: U128,
     ) -> (U128, U128) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let (tick_lower, tick_upper) = Self::_order_ticks(tick_lower, tick_upper, flipped);
+        let (amount0_requested, amount1_requested) = Self::_order((amount0_requested, amount1_requested), flipped);
         let pool_key = (token0, token1, fee);
-        assert!(self.pools.get(&pool_key).is_some(), "Pool does not exist");
+        self._lock(pool_key);
 
         let caller = self.env().caller();
         let pool_id = Self::compute_pool_id(token0, token1, fee);
         let position_key = Self::compute_position_key(caller, tick_lower, tick_upper);
-        
+
+        // Checkpoint fees earned since the last update so they can be collected
+        let pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        let position = self.positions.get(&(pool_id, position_key)).unwrap_or_default();
+        if !position.liquidity.is_zero() {
+            self._update_position(pool_id, &pool_data, caller, tick_lower, tick_upper, 0);
+        }
+
         let mut position = self.positions.get(&(pool_id, position_key)).unwrap_or_default();
 
         let amount0 = amount0_requested.min(position.tokens_owed_0);
@@ -344,16 +568,23 @@ impl UnifiedDex {
         if !amount0.is_zero() {
             let mut token0_contract = Erc20ContractRef::new(self.env(), token0);
             let amount0_u256 = U256::from(amount0.as_u128());
+            self.reserves.debit(pool_id, token0, amount0_u256);
             token0_contract.transfer(&recipient, &amount0_u256);
         }
 
         if !amount1.is_zero() {
             let mut token1_contract = Erc20ContractRef::new(self.env(), token1);
             let amount1_u256 = U256::from(amount1.as_u128());
+            self.reserves.debit(pool_id, token1, amount1_u256);
             token1_contract.transfer(&recipient, &amount1_u256);
         }
 
         self.env().emit_event(Collect {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
             owner: caller,
             recipient,
             tick_lower,
@@ -362,10 +593,12 @@ impl UnifiedDex {
             amount1,
         });
 
-        (amount0, amount1)
+        self._unlock(pool_key);
+
+        Self::_order((amount0, amount1), flipped)
     }
 
-    /// Swap tokens in a pool
+    /// Swap tokens in a pool, pulling the input from the caller with `transfer_from`
     pub fn swap(
         &mut self,
         token0: Address,
@@ -373,14 +606,71 @@ impl UnifiedDex {
         fee: u32,
         recipient: Address,
         zero_for_one: bool,
-        amount_specified: i64,
+        amount_specified: I128,
+        sqrt_price_limit_x96: U256,
+    ) -> (I128, I128) {
+        self._swap(token0, token1, fee, recipient, zero_for_one, amount_specified, sqrt_price_limit_x96, None)
+    }
+
+    /// Swap tokens in a pool, sending the output first and letting the caller pay the input
+    ///
+    /// The caller must be a contract implementing `UnifiedDexSwapCallback`. Its
+    /// `swap_callback` receives the signed deltas owed to (positive) and by (negative)
+    /// the pool and must transfer the positive delta to the DEX before returning.
+    ///
+    /// # Arguments
+    /// * `data` - Opaque data passed through to the callback
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_with_callback(
+        &mut self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        recipient: Address,
+        zero_for_one: bool,
+        amount_specified: I128,
+        sqrt_price_limit_x96: U256,
+        data: Bytes,
+    ) -> (I128, I128) {
+        self._swap(token0, token1, fee, recipient, zero_for_one, amount_specified, sqrt_price_limit_x96, Some(data))
+    }
+
+    /// Swap tokens in a pool and collect the input, either with `transfer_from`
+    /// (`data` is `None`) or through the caller's swap callback
+    #[allow(clippy::too_many_arguments)]
+    fn _swap(
+        &mut self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        recipient: Address,
+        zero_for_one: bool,
+        amount_specified: I128,
         sqrt_price_limit_x96: U256,
-    ) -> (i64, i64) {
+        data: Option<Bytes>,
+    ) -> (I128, I128) {
+        let amount_specified = amount_specified.as_i128();
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        self.guard.assert_not_paused(Self::compute_pool_id(token0, token1, fee), PAUSE_SWAP);
+        let zero_for_one = zero_for_one != flipped;
+        let sqrt_price_limit_x96 = if flipped && !sqrt_price_limit_x96.is_zero() {
+            Self::_invert_sqrt_price(sqrt_price_limit_x96).unwrap_or_revert(self).clamp(
+                U256::from(MIN_SQRT_RATIO + 1),
+                U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap() - U256::one(),
+            )
+        } else {
+            sqrt_price_limit_x96
+        };
         let pool_key = (token0, token1, fee);
-        let mut pool_data = self.pools.get(&pool_key).expect("Pool does not exist");
+        self._lock(pool_key);
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
 
-        assert!(!pool_data.slot0.sqrt_price_x96.is_zero(), "Pool not initialized");
-        assert!(amount_specified != 0, "Amount cannot be zero");
+        if pool_data.slot0.sqrt_price_x96.is_zero() {
+            self.env().revert(Error::PoolNotInitialized);
+        }
+        if amount_specified == 0 {
+            self.env().revert(Error::ZeroAmount);
+        }
 
         // Set price limit to min/max if not specified
         let sqrt_price_limit = if sqrt_price_limit_x96.is_zero() {
@@ -395,309 +685,412 @@ impl UnifiedDex {
 
         // Validate price limit
         if zero_for_one {
-            assert!(sqrt_price_limit < pool_data.slot0.sqrt_price_x96, "Price limit too high");
-            assert!(sqrt_price_limit > U256::from(MIN_SQRT_RATIO), "Price limit too low");
+            if sqrt_price_limit >= pool_data.slot0.sqrt_price_x96 {
+                self.env().revert(Error::PriceLimitTooHigh);
+            }
+            if sqrt_price_limit <= U256::from(MIN_SQRT_RATIO) {
+                self.env().revert(Error::PriceLimitTooLow);
+            }
         } else {
-            assert!(sqrt_price_limit > pool_data.slot0.sqrt_price_x96, "Price limit too low");
-            assert!(sqrt_price_limit < U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap(), "Price limit too high");
+            if sqrt_price_limit <= pool_data.slot0.sqrt_price_x96 {
+                self.env().revert(Error::PriceLimitTooLow);
+            }
+            if sqrt_price_limit >= U256::from_dec_str(MAX_SQRT_RATIO_STR).unwrap() {
+                self.env().revert(Error::PriceLimitTooHigh);
+            }
         }
 
         let exact_input = amount_specified > 0;
-
-        // Initialize swap state with tick crossing support
-        let mut amount_specified_remaining = amount_specified.abs() as u128;
-        let mut amount_calculated: u128 = 0;
-        let mut sqrt_price_x96 = pool_data.slot0.sqrt_price_x96;
-        let mut current_liquidity = pool_data.liquidity;
-        let mut current_tick = pool_data.slot0.tick;
         let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let slot0_start = pool_data.slot0.clone();
+        let liquidity_start = pool_data.liquidity;
 
-        // V3 tick-crossing swap loop
-        while amount_specified_remaining > 0 && sqrt_price_x96 != sqrt_price_limit {
-            // Check if we have liquidity to trade against
-            if current_liquidity.is_zero() {
-                break; // No liquidity available
-            }
+        // Protocol's share of the fee for the input token (0 = switched off)
+        let fee_protocol = if zero_for_one {
+            pool_data.slot0.fee_protocol % 16
+        } else {
+            pool_data.slot0.fee_protocol >> 4
+        };
 
-            let sqrt_price_start = sqrt_price_x96;
-
-            // Compute swap step within current tick range
-            let amount_in_u256 = U256::from(amount_specified_remaining);
-
-            // Apply fee
-            let fee_amount = amount_in_u256
-                .checked_mul(U256::from(pool_data.fee))
-                .expect("Fee multiplication overflow")
-                .checked_div(U256::from(1_000_000u32))
-                .expect("Fee division failed");
-            let amount_in_after_fee = amount_in_u256.checked_sub(fee_amount).expect("Fee subtraction underflow");
-
-            // Calculate next price (limited by price limit)
-            let sqrt_price_target = sqrt_price_limit;
-
-            sqrt_price_x96 = if exact_input {
-                crate::math::SqrtPriceMath::get_next_sqrt_price_from_input(
-                    sqrt_price_x96,
-                    current_liquidity,
-                    amount_in_after_fee,
-                    zero_for_one,
-                )
+        // Oracle accumulators as of this block, recorded on every tick crossed
+        let block_timestamp = self._block_timestamp();
+        let (tick_cumulative, seconds_per_liquidity_cumulative_x128) =
+            self._observe_current(pool_id, &pool_data, block_timestamp);
+
+        let mut state = SwapState {
+            amount_specified_remaining: amount_specified,
+            amount_calculated: 0,
+            sqrt_price_x96: slot0_start.sqrt_price_x96,
+            tick: slot0_start.tick,
+            fee_growth_global_x128: if zero_for_one {
+                pool_data.fee_growth_global_0_x128
             } else {
-                crate::math::SqrtPriceMath::get_next_sqrt_price_from_output(
-                    sqrt_price_x96,
-                    current_liquidity,
-                    amount_in_u256,
-                    zero_for_one,
-                )
-            };
+                pool_data.fee_growth_global_1_x128
+            },
+            protocol_fee: U256::zero(),
+            liquidity: liquidity_start,
+        };
 
-            // Clamp to price limit (don't cross it)
-            let hit_price_limit = if zero_for_one {
-                let clamped = sqrt_price_x96.max(sqrt_price_target);
-                let hit_limit = clamped != sqrt_price_x96;
-                sqrt_price_x96 = clamped;
-                hit_limit
-            } else {
-                let clamped = sqrt_price_x96.min(sqrt_price_target);
-                let hit_limit = clamped != sqrt_price_x96;
-                sqrt_price_x96 = clamped;
-                hit_limit
+        // Continue swapping as long as we haven't used the entire input/output and haven't reached the price limit
+        while state.amount_specified_remaining != 0 && state.sqrt_price_x96 != sqrt_price_limit {
+            let mut step = StepComputations {
+                sqrt_price_start_x96: state.sqrt_price_x96,
+                tick_next: 0,
+                initialized: false,
+                sqrt_price_next_x96: U256::zero(),
+                amount_in: U256::zero(),
+                amount_out: U256::zero(),
+                fee_amount: U256::zero(),
             };
 
-            // Calculate amounts for this step
-            let (amount_in_step, amount_out_step) = if zero_for_one {
-                let amt_in = crate::math::SqrtPriceMath::get_amount0_delta(
-                    sqrt_price_x96,
-                    sqrt_price_start,
-                    current_liquidity,
-                    true, // round up for amount in
-                );
-                let amt_out = crate::math::SqrtPriceMath::get_amount1_delta(
-                    sqrt_price_x96,
-                    sqrt_price_start,
-                    current_liquidity,
-                    false, // round down for amount out
-                );
-                (amt_in, amt_out)
+            // Step to the next initialized tick, or the end of the searched word; each
+            // iteration walks at most one word, until MIN_TICK/MAX_TICK
+            let (tick_next, initialized) = self._find_next_initialized_tick(
+                pool_id,
+                state.tick,
+                pool_data.tick_spacing,
+                zero_for_one,
+            );
+            step.tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+            step.initialized = initialized;
+            step.sqrt_price_next_x96 = TickMath::get_sqrt_ratio_at_tick(step.tick_next).unwrap_or_revert(self);
+
+            // Swap towards the next tick, but never past the price limit
+            let sqrt_price_target = if (zero_for_one && step.sqrt_price_next_x96 < sqrt_price_limit)
+                || (!zero_for_one && step.sqrt_price_next_x96 > sqrt_price_limit)
+            {
+                sqrt_price_limit
             } else {
-                let amt_in = crate::math::SqrtPriceMath::get_amount1_delta(
-                    sqrt_price_start,
-                    sqrt_price_x96,
-                    current_liquidity,
-                    true, // round up for amount in
-                );
-                let amt_out = crate::math::SqrtPriceMath::get_amount0_delta(
-                    sqrt_price_start,
-                    sqrt_price_x96,
-                    current_liquidity,
-                    false, // round down for amount out
-                );
-                (amt_in, amt_out)
+                step.sqrt_price_next_x96
             };
-
-            // Update remaining amount and calculated amount
-            let amount_in_with_fee = amount_in_step.as_u128();
-            if amount_in_with_fee >= amount_specified_remaining {
-                amount_specified_remaining = 0;
+            (state.sqrt_price_x96, step.amount_in, step.amount_out, step.fee_amount) = SwapMath::compute_swap_step(
+                state.sqrt_price_x96,
+                sqrt_price_target,
+                state.liquidity,
+                state.amount_specified_remaining,
+                pool_data.fee,
+            ).unwrap_or_revert(self);
+
+            let amount_in_with_fee = i128::try_from(step.amount_in + step.fee_amount)
+                .ok()
+                .unwrap_or_revert_with(self, Error::AmountOverflow);
+            let amount_out = i128::try_from(step.amount_out).ok().unwrap_or_revert_with(self, Error::AmountOverflow);
+            if exact_input {
+                state.amount_specified_remaining -= amount_in_with_fee;
+                state.amount_calculated -= amount_out;
             } else {
-                amount_specified_remaining -= amount_in_with_fee;
-            }
-            amount_calculated += amount_out_step.as_u128();
-
-            // Update fee growth
-            if !fee_amount.is_zero() && !current_liquidity.is_zero() {
-                let step_fee = amount_in_step
-                    .checked_mul(U256::from(pool_data.fee))
-                    .expect("Step fee overflow")
-                    .checked_div(U256::from(1_000_000u32))
-                    .unwrap_or(U256::zero());
-
-                let fee_growth_delta = (step_fee << 128)
-                    .checked_div(U256::from(current_liquidity.as_u128()))
-                    .unwrap_or(U256::zero());
-
-                if zero_for_one {
-                    pool_data.fee_growth_global_0_x128 = pool_data.fee_growth_global_0_x128 + fee_growth_delta;
-                } else {
-                    pool_data.fee_growth_global_1_x128 = pool_data.fee_growth_global_1_x128 + fee_growth_delta;
-                }
+                state.amount_specified_remaining += amount_out;
+                state.amount_calculated += amount_in_with_fee;
             }
 
-            // Check if we hit price limit - if so, stop
-            if hit_price_limit {
-                break;
+            // Carve out the protocol fee before crediting LPs
+            if fee_protocol > 0 {
+                let delta = step.fee_amount / U256::from(fee_protocol);
+                step.fee_amount -= delta;
+                state.protocol_fee += delta;
             }
 
-            // Tick crossing logic
-            // Calculate what tick corresponds to the new price
-            let tick_at_new_price = crate::math::TickMath::get_tick_at_sqrt_ratio(sqrt_price_x96);
-
-            // Check if we crossed a tick boundary
-            if zero_for_one {
-                // Moving down in price (left in tick space)
-                // Check if we crossed the lower tick boundary
-                if tick_at_new_price < current_tick {
-                    // We've crossed one or more ticks - need to update liquidity
-                    // Find the next initialized tick
-                    let (next_tick, initialized) = self._find_next_initialized_tick(
-                        pool_id,
-                        current_tick,
-                        true // searching downward
-                    );
-
-                    if initialized && tick_at_new_price <= next_tick {
-                        // Cross the tick and update liquidity
-                        let tick_info = self.ticks.get(&(pool_id, next_tick)).unwrap_or_default();
-                        let liquidity_net = tick_info.liquidity_net.as_i128();
-
-                        // When crossing left to right, we subtract liquidity_net
-                        // When crossing right to left (our case), we add liquidity_net
-                        current_liquidity = if liquidity_net >= 0 {
-                            U128::from(current_liquidity.as_u128() + liquidity_net as u128)
-                        } else {
-                            U128::from(current_liquidity.as_u128() - (-liquidity_net) as u128)
-                        };
+            // Update global fee tracker
+            if !state.liquidity.is_zero() {
+                let fee_growth_delta = FullMath::mul_div(
+                    step.fee_amount,
+                    U256::from_dec_str(Q128_STR).unwrap(),
+                    U256::from(state.liquidity.as_u128()),
+                ).unwrap_or_revert(self);
+                state.fee_growth_global_x128 = state.fee_growth_global_x128.overflowing_add(fee_growth_delta).0;
+            }
 
-                        current_tick = next_tick - 1; // Move below the crossed tick
+            if state.sqrt_price_x96 == step.sqrt_price_next_x96 {
+                // Reached the next tick: cross it if initialized
+                if step.initialized {
+                    let (fee_growth_global_0_x128, fee_growth_global_1_x128) = if zero_for_one {
+                        (state.fee_growth_global_x128, pool_data.fee_growth_global_1_x128)
                     } else {
-                        current_tick = tick_at_new_price;
-                    }
-                } else {
-                    current_tick = tick_at_new_price;
-                }
-            } else {
-                // Moving up in price (right in tick space)
-                // Check if we crossed the upper tick boundary
-                if tick_at_new_price > current_tick {
-                    // We've crossed one or more ticks - need to update liquidity
-                    // Find the next initialized tick
-                    let (next_tick, initialized) = self._find_next_initialized_tick(
+                        (pool_data.fee_growth_global_0_x128, state.fee_growth_global_x128)
+                    };
+                    let mut liquidity_net = self._cross_tick(
                         pool_id,
-                        current_tick,
-                        false // searching upward
+                        step.tick_next,
+                        fee_growth_global_0_x128,
+                        fee_growth_global_1_x128,
+                        seconds_per_liquidity_cumulative_x128,
+                        tick_cumulative,
+                        block_timestamp,
                     );
-
-                    if initialized && tick_at_new_price >= next_tick {
-                        // Cross the tick and update liquidity
-                        let tick_info = self.ticks.get(&(pool_id, next_tick)).unwrap_or_default();
-                        let liquidity_net = tick_info.liquidity_net.as_i128();
-
-                        // When crossing left to right (our case), we add liquidity_net
-                        current_liquidity = if liquidity_net >= 0 {
-                            U128::from(current_liquidity.as_u128() + liquidity_net as u128)
-                        } else {
-                            U128::from(current_liquidity.as_u128() - (-liquidity_net) as u128)
-                        };
-
-                        current_tick = next_tick; // Move to the crossed tick
-                    } else {
-                        current_tick = tick_at_new_price;
+                    // Moving leftward, liquidity_net is interpreted as the opposite sign
+                    if zero_for_one {
+                        liquidity_net = -liquidity_net;
                     }
-                } else {
-                    current_tick = tick_at_new_price;
+                    state.liquidity = LiquidityMath::add_delta(state.liquidity, liquidity_net).unwrap_or_revert(self);
                 }
+
+                state.tick = if zero_for_one { step.tick_next - 1 } else { step.tick_next };
+            } else if state.sqrt_price_x96 != step.sqrt_price_start_x96 {
+                // Recompute unless we're on a lower tick boundary and haven't moved
+                state.tick = TickMath::get_tick_at_sqrt_ratio(state.sqrt_price_x96).unwrap_or_revert(self);
             }
         }
 
-        // Update pool state
-        pool_data.slot0.sqrt_price_x96 = sqrt_price_x96;
-        pool_data.slot0.tick = crate::math::TickMath::get_tick_at_sqrt_ratio(sqrt_price_x96);
+        // Record the pre-swap tick and liquidity for the elapsed interval, then move the price
+        if state.tick != slot0_start.tick {
+            pool_data.slot0 = self._write_observation(
+                pool_id,
+                pool_data.slot0,
+                slot0_start.tick,
+                liquidity_start,
+            );
+        }
+        pool_data.slot0.sqrt_price_x96 = state.sqrt_price_x96;
+        pool_data.slot0.tick = state.tick;
+        pool_data.liquidity = state.liquidity;
 
-        // Update TWAP oracle observation
-        let current_tick = pool_data.slot0.tick;
-        pool_data.slot0 = self._write_observation(
-            pool_id,
-            pool_data.slot0,
-            current_tick,
-            current_liquidity,
-        );
+        if zero_for_one {
+            pool_data.fee_growth_global_0_x128 = state.fee_growth_global_x128;
+            pool_data.protocol_fees_token0 = self._add_owed(pool_data.protocol_fees_token0, state.protocol_fee);
+        } else {
+            pool_data.fee_growth_global_1_x128 = state.fee_growth_global_x128;
+            pool_data.protocol_fees_token1 = self._add_owed(pool_data.protocol_fees_token1, state.protocol_fee);
+        }
 
         self.pools.set(&pool_key, pool_data);
 
         // Calculate final amounts
-        let (amount0, amount1) = if zero_for_one {
-            (amount_specified, -(amount_calculated as i64))
+        let amount_consumed = amount_specified - state.amount_specified_remaining;
+        let (amount0, amount1) = if zero_for_one == exact_input {
+            (amount_consumed, state.amount_calculated)
         } else {
-            (-(amount_calculated as i64), amount_specified)
+            (state.amount_calculated, amount_consumed)
         };
 
-        // Transfer tokens with fee-on-transfer protection
         let caller = self.env().caller();
         let dex_address = self.env().self_address();
 
-        if amount0 > 0 {
-            // User sends token0 to DEX
-            let mut token0_contract = Erc20ContractRef::new(self.env(), token0);
-            let balance_before = token0_contract.balance_of(&dex_address);
-            token0_contract.transfer_from(&caller, &dex_address, &U256::from(amount0 as u128));
-            let balance_after = token0_contract.balance_of(&dex_address);
-            let received = balance_after - balance_before;
-            assert!(received >= U256::from(amount0 as u128), "Fee-on-transfer tokens not supported");
-        } else if amount0 < 0 {
+        if amount0 < 0 {
             // DEX sends token0 to user
             let mut token0_contract = Erc20ContractRef::new(self.env(), token0);
-            token0_contract.transfer(&recipient, &U256::from((-amount0) as u128));
+            let amount0_out = U256::from(amount0.unsigned_abs());
+            self.reserves.debit(pool_id, token0, amount0_out);
+            token0_contract.transfer(&recipient, &amount0_out);
         }
 
-        if amount1 > 0 {
-            // User sends token1 to DEX
-            let mut token1_contract = Erc20ContractRef::new(self.env(), token1);
-            let balance_before = token1_contract.balance_of(&dex_address);
-            token1_contract.transfer_from(&caller, &dex_address, &U256::from(amount1 as u128));
-            let balance_after = token1_contract.balance_of(&dex_address);
-            let received = balance_after - balance_before;
-            assert!(received >= U256::from(amount1 as u128), "Fee-on-transfer tokens not supported");
-        } else if amount1 < 0 {
+        if amount1 < 0 {
             // DEX sends token1 to user
             let mut token1_contract = Erc20ContractRef::new(self.env(), token1);
-            token1_contract.transfer(&recipient, &U256::from((-amount1) as u128));
+            let amount1_out = U256::from(amount1.unsigned_abs());
+            self.reserves.debit(pool_id, token1, amount1_out);
+            token1_contract.transfer(&recipient, &amount1_out);
         }
 
-        (amount0, amount1)
+        // Collect the input with fee-on-transfer protection
+        let (token_in, amount_in) = if amount0 > 0 {
+            (token0, U256::from(amount0 as u128))
+        } else {
+            (token1, U256::from(amount1.max(0) as u128))
+        };
+        if !amount_in.is_zero() {
+            let unaccounted_before = self.reserves.unaccounted(token_in);
+            match data {
+                Some(data) => {
+                    if !caller.is_contract() {
+                        self.env().revert(Error::CallerNotContract);
+                    }
+                    let (amount_a_delta, amount_b_delta) = Self::_order((amount0, amount1), flipped);
+                    UnifiedDexSwapCallbackContractRef::new(self.env(), caller).swap_callback(
+                        I128::from_i128(amount_a_delta),
+                        I128::from_i128(amount_b_delta),
+                        data,
+                    );
+                }
+                None => Erc20ContractRef::new(self.env(), token_in).transfer_from(&caller, &dex_address, &amount_in),
+            }
+            let received = self.reserves.unaccounted(token_in).saturating_sub(unaccounted_before);
+            if received < amount_in {
+                self.env().revert(Error::InsufficientPayment);
+            }
+            self.reserves.credit(pool_id, token_in, received);
+        }
+
+        self.env().emit_event(Swap {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
+            sender: caller,
+            recipient,
+            amount0: I128::from_i128(amount0),
+            amount1: I128::from_i128(amount1),
+            sqrt_price_x96: state.sqrt_price_x96,
+            liquidity: state.liquidity,
+            tick: state.tick,
+        });
+
+        self._unlock(pool_key);
+
+        let (amount_a, amount_b) = Self::_order((amount0, amount1), flipped);
+        (I128::from_i128(amount_a), I128::from_i128(amount_b))
     }
-    fn _modify_position(
+
+    /// Borrow pool reserves and repay them, plus the pool fee, within the same call
+    ///
+    /// The caller must be a contract implementing `UnifiedDexFlashCallback`. The fee
+    /// paid is credited to in-range LPs (less the protocol share) through fee growth.
+    /// Each amount is capped at the pool's own reserve of the token (`get_pool_reserves`).
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `recipient` - Address receiving the borrowed tokens
+    /// * `amount0` - Amount of token0 to borrow
+    /// * `amount1` - Amount of token1 to borrow
+    /// * `data` - Opaque data passed through to the callback
+    #[allow(clippy::too_many_arguments)]
+    pub fn flash(
         &mut self,
-        pool_key: (Address, Address, u32),
-        pool_id: [u8; 32],
-        owner: Address,
-        tick_lower: i32,
-        tick_upper: i32,
-        liquidity_delta: i64,
-    ) -> (U256, U256) {
-        if liquidity_delta == 0 {
-            return (U256::zero(), U256::zero());
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        recipient: Address,
+        amount0: U256,
+        amount1: U256,
+        data: Bytes,
+    ) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        self.guard.assert_not_paused(Self::compute_pool_id(token0, token1, fee), PAUSE_FLASH);
+        let (amount0, amount1) = Self::_order((amount0, amount1), flipped);
+        let pool_key = (token0, token1, fee);
+        self._lock(pool_key);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        if pool_data.liquidity.is_zero() {
+            self.env().revert(Error::ZeroLiquidity);
         }
 
-        // Get pool data to access current price
-        let pool_data = self.pools.get(&pool_key).expect("Pool does not exist");
-        let sqrt_price_current = pool_data.slot0.sqrt_price_x96;
-        let current_tick = pool_data.slot0.tick;
+        if amount0 > self.reserves.pool_reserve(pool_id, token0) || amount1 > self.reserves.pool_reserve(pool_id, token1) {
+            self.env().revert(Error::InsufficientReserves);
+        }
 
-        // Update tick data (track liquidity changes at tick boundaries)
-        self._update_tick(pool_id, tick_lower, liquidity_delta, current_tick);
-        self._update_tick(pool_id, tick_upper, liquidity_delta, current_tick);
+        let fee0 = FullMath::mul_div_rounding_up(amount0, U256::from(fee), U256::from(1_000_000u32)).unwrap_or_revert(self);
+        let fee1 = FullMath::mul_div_rounding_up(amount1, U256::from(fee), U256::from(1_000_000u32)).unwrap_or_revert(self);
 
-        // Get position key
-        let position_key = Self::compute_position_key(owner, tick_lower, tick_upper);
+        let caller = self.env().caller();
+        if !caller.is_contract() {
+            self.env().revert(Error::CallerNotContract);
+        }
 
-        // Get or create position
-        let mut position = self.positions.get(&(pool_id, position_key)).unwrap_or_default();
+        let unaccounted0_before = self.reserves.unaccounted(token0);
+        let unaccounted1_before = self.reserves.unaccounted(token1);
 
-        // Update position liquidity
-        position.liquidity = if liquidity_delta >= 0 {
-            position.liquidity + (liquidity_delta as u128)
-        } else {
-            let delta_abs = U128::from((-liquidity_delta) as u128);
-            assert!(position.liquidity >= delta_abs, "Insufficient liquidity");
-            position.liquidity - delta_abs.as_u128()
-        };
+        if !amount0.is_zero() {
+            self.reserves.debit(pool_id, token0, amount0);
+            Erc20ContractRef::new(self.env(), token0).transfer(&recipient, &amount0);
+        }
+        if !amount1.is_zero() {
+            self.reserves.debit(pool_id, token1, amount1);
+            Erc20ContractRef::new(self.env(), token1).transfer(&recipient, &amount1);
+        }
 
-        // Save updated position
-        self.positions.set(&(pool_id, position_key), position);
+        let (fee_a, fee_b) = Self::_order((fee0, fee1), flipped);
+        UnifiedDexFlashCallbackContractRef::new(self.env(), caller).flash_callback(fee_a, fee_b, data);
+
+        let owed0 = amount0.checked_add(fee0).unwrap_or_revert_with(self, Error::MathOverflow);
+        let owed1 = amount1.checked_add(fee1).unwrap_or_revert_with(self, Error::MathOverflow);
+        let received0 = self.reserves.unaccounted(token0).saturating_sub(unaccounted0_before);
+        let received1 = self.reserves.unaccounted(token1).saturating_sub(unaccounted1_before);
+        if received0 < owed0 || received1 < owed1 {
+            self.env().revert(Error::FlashLoanNotRepaid);
+        }
+        self.reserves.credit(pool_id, token0, received0);
+        self.reserves.credit(pool_id, token1, received1);
+
+        let paid0 = received0 - amount0;
+        let paid1 = received1 - amount1;
+
+        // The pool is locked during the callback, so its liquidity is unchanged
+        let liquidity = U256::from(pool_data.liquidity.as_u128());
+
+        if !paid0.is_zero() {
+            let fee_protocol_0 = pool_data.slot0.fee_protocol % 16;
+            let protocol_fee_0 = if fee_protocol_0 == 0 {
+                U256::zero()
+            } else {
+                paid0 / U256::from(fee_protocol_0)
+            };
+            pool_data.protocol_fees_token0 = self._add_owed(pool_data.protocol_fees_token0, protocol_fee_0);
+            pool_data.fee_growth_global_0_x128 = pool_data
+                .fee_growth_global_0_x128
+                .overflowing_add(FullMath::mul_div(paid0 - protocol_fee_0, U256::one() << 128, liquidity).unwrap_or_revert(self))
+                .0;
+        }
+        if !paid1.is_zero() {
+            let fee_protocol_1 = pool_data.slot0.fee_protocol >> 4;
+            let protocol_fee_1 = if fee_protocol_1 == 0 {
+                U256::zero()
+            } else {
+                paid1 / U256::from(fee_protocol_1)
+            };
+            pool_data.protocol_fees_token1 = self._add_owed(pool_data.protocol_fees_token1, protocol_fee_1);
+            pool_data.fee_growth_global_1_x128 = pool_data
+                .fee_growth_global_1_x128
+                .overflowing_add(FullMath::mul_div(paid1 - protocol_fee_1, U256::one() << 128, liquidity).unwrap_or_revert(self))
+                .0;
+        }
+
+        self.pools.set(&pool_key, pool_data);
+
+        self.env().emit_event(Flash {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
+            sender: caller,
+            recipient,
+            amount0,
+            amount1,
+            paid0,
+            paid1,
+        });
+
+        self._unlock(pool_key);
+    }
+
+    /// Apply a liquidity change to a position and to the in-range pool liquidity
+    ///
+    /// # Returns
+    /// The amounts of token0 and token1 owed to the pool (mint) or by the pool (burn)
+    fn _modify_position(
+        &mut self,
+        pool_key: (Address, Address, u32),
+        pool_id: [u8; 32],
+        owner: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> (U256, U256) {
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+
+        // Update ticks and checkpoint the position's fees
+        self._update_position(
+            pool_id,
+            &pool_data,
+            owner,
+            tick_lower,
+            tick_upper,
+            liquidity_delta,
+        );
+
+        if liquidity_delta == 0 {
+            return (U256::zero(), U256::zero());
+        }
+
+        let sqrt_price_current = pool_data.slot0.sqrt_price_x96;
+        let current_tick = pool_data.slot0.tick;
 
         // Get tick prices
-        let sqrt_price_lower = crate::math::TickMath::get_sqrt_ratio_at_tick(tick_lower);
-        let sqrt_price_upper = crate::math::TickMath::get_sqrt_ratio_at_tick(tick_upper);
+        let sqrt_price_lower = crate::math::TickMath::get_sqrt_ratio_at_tick(tick_lower).unwrap_or_revert(self);
+        let sqrt_price_upper = crate::math::TickMath::get_sqrt_ratio_at_tick(tick_upper).unwrap_or_revert(self);
 
         let liquidity_abs = U128::from(liquidity_delta.unsigned_abs());
 
@@ -709,7 +1102,7 @@ impl UnifiedDex {
                 sqrt_price_upper,
                 liquidity_abs,
                 liquidity_delta > 0,
-            );
+            ).unwrap_or_revert(self);
             (amount0, U256::zero())
         } else if sqrt_price_current < sqrt_price_upper {
             // Current price in range - both tokens needed
@@ -718,13 +1111,13 @@ impl UnifiedDex {
                 sqrt_price_upper,
                 liquidity_abs,
                 liquidity_delta > 0,
-            );
+            ).unwrap_or_revert(self);
             let amount1 = crate::math::SqrtPriceMath::get_amount1_delta(
                 sqrt_price_lower,
                 sqrt_price_current,
                 liquidity_abs,
                 liquidity_delta > 0,
-            );
+            ).unwrap_or_revert(self);
             (amount0, amount1)
         } else {
             // Current price above range - only token1 needed
@@ -733,237 +1126,221 @@ impl UnifiedDex {
                 sqrt_price_upper,
                 liquidity_abs,
                 liquidity_delta > 0,
-            );
+            ).unwrap_or_revert(self);
             (U256::zero(), amount1)
         };
 
+        // Active liquidity only changes if the position is in range
+        if tick_lower <= current_tick && current_tick < tick_upper {
+            // Record the oracle observation before the liquidity it accumulates over changes
+            let current_liquidity = pool_data.liquidity;
+            pool_data.slot0 = self._write_observation(
+                pool_id,
+                pool_data.slot0,
+                current_tick,
+                current_liquidity,
+            );
+            pool_data.liquidity = LiquidityMath::add_delta(current_liquidity, liquidity_delta).unwrap_or_revert(self);
+            self.pools.set(&pool_key, pool_data);
+        }
+
         (amount0, amount1)
     }
 
-    /// Update tick data when liquidity changes at a tick boundary
-    fn _update_tick(
+    /// Update the ticks bounding a position and checkpoint the fees it has earned
+    ///
+    /// # Arguments
+    /// * `pool_id` - The pool identifier
+    /// * `pool_data` - The pool state before the liquidity change
+    /// * `owner` - The owner of the position
+    /// * `tick_lower` - The lower tick of the position
+    /// * `tick_upper` - The upper tick of the position
+    /// * `liquidity_delta` - Change in liquidity (0 only accrues fees)
+    fn _update_position(
         &mut self,
         pool_id: [u8; 32],
-        tick: i32,
-        liquidity_delta: i64,
-        _current_tick: i32, // Reserved for future tick crossing implementation
+        pool_data: &PoolData,
+        owner: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
     ) {
-        let mut tick_info = self.ticks.get(&(pool_id, tick)).unwrap_or_default();
+        let position_key = Self::compute_position_key(owner, tick_lower, tick_upper);
+        let mut position = self.positions.get(&(pool_id, position_key)).unwrap_or_default();
 
-        let liquidity_gross_before = tick_info.liquidity_gross;
+        if liquidity_delta < 0 && position.liquidity < U128::from(liquidity_delta.unsigned_abs()) {
+            self.env().revert(Error::LiquidityUnderflow);
+        }
 
-        // Update liquidity gross (total liquidity referencing this tick) with checked arithmetic
-        tick_info.liquidity_gross = if liquidity_delta >= 0 {
-            tick_info.liquidity_gross + (liquidity_delta as u128)
+        let (flipped_lower, flipped_upper) = if liquidity_delta != 0 {
+            (
+                self._update_tick(pool_id, pool_data, tick_lower, liquidity_delta, false),
+                self._update_tick(pool_id, pool_data, tick_upper, liquidity_delta, true),
+            )
         } else {
-            let delta_abs = U128::from((-liquidity_delta) as u128);
-            assert!(tick_info.liquidity_gross >= delta_abs, "Insufficient tick liquidity");
-            tick_info.liquidity_gross - delta_abs.as_u128()
+            (false, false)
         };
 
-        // Update liquidity net (liquidity change when crossing this tick)
-        // When crossing from left to right, add liquidity_net
-        // When crossing from right to left, subtract liquidity_net
-        let current_net = tick_info.liquidity_net.as_i128();
-        tick_info.liquidity_net = I128::from_i128(current_net + liquidity_delta as i128);
+        let lower = self.ticks.get(&(pool_id, tick_lower)).unwrap_or_default();
+        let upper = self.ticks.get(&(pool_id, tick_upper)).unwrap_or_default();
+        let (fee_growth_inside_0_x128, fee_growth_inside_1_x128) = Tick::get_fee_growth_inside(
+            &lower,
+            &upper,
+            tick_lower,
+            tick_upper,
+            pool_data.slot0.tick,
+            pool_data.fee_growth_global_0_x128,
+            pool_data.fee_growth_global_1_x128,
+        );
+
+        position
+            .update(liquidity_delta, fee_growth_inside_0_x128, fee_growth_inside_1_x128)
+            .unwrap_or_revert(self);
+        self.positions.set(&(pool_id, position_key), position);
+
+        // Clear any tick data that is no longer needed, so a later re-initialization
+        // starts from fresh outside accumulators
+        if liquidity_delta < 0 {
+            if flipped_lower {
+                self._clear_tick(pool_id, tick_lower);
+            }
+            if flipped_upper {
+                self._clear_tick(pool_id, tick_upper);
+            }
+        }
+    }
+
+    /// Update tick data when liquidity changes at a tick boundary
+    ///
+    /// # Returns
+    /// True if the tick was flipped between initialized and uninitialized
+    fn _update_tick(
+        &mut self,
+        pool_id: [u8; 32],
+        pool_data: &PoolData,
+        tick: i32,
+        liquidity_delta: i128,
+        upper: bool,
+    ) -> bool {
+        let block_timestamp = self._block_timestamp();
+        let (tick_cumulative, seconds_per_liquidity_cumulative_x128) =
+            self._observe_current(pool_id, pool_data, block_timestamp);
+
+        let mut tick_info = self.ticks.get(&(pool_id, tick)).unwrap_or_default();
 
-        // If this tick now has liquidity and didn't before, flip it in the bitmap
-        if liquidity_gross_before == U128::zero() && tick_info.liquidity_gross > U128::zero() {
-            self._flip_tick_in_bitmap(pool_id, tick);
-            tick_info.initialized = true;
-        } else if liquidity_gross_before > U128::zero() && tick_info.liquidity_gross == U128::zero() {
-            // If tick no longer has liquidity, flip it off
-            self._flip_tick_in_bitmap(pool_id, tick);
-            tick_info.initialized = false;
+        let flipped = tick_info.update(
+            tick,
+            pool_data.slot0.tick,
+            liquidity_delta,
+            pool_data.fee_growth_global_0_x128,
+            pool_data.fee_growth_global_1_x128,
+            seconds_per_liquidity_cumulative_x128,
+            tick_cumulative,
+            block_timestamp,
+            upper,
+            pool_data.max_liquidity_per_tick,
+        ).unwrap_or_revert(self);
+
+        // Keep the bitmap in sync so swaps can find the tick
+        if flipped {
+            self._flip_tick_in_bitmap(pool_id, tick, pool_data.tick_spacing);
+            tick_info.initialized = !tick_info.liquidity_gross.is_zero();
         }
 
         self.ticks.set(&(pool_id, tick), tick_info);
+        flipped
+    }
+
+    /// Reset a deinitialized tick to its default state
+    fn _clear_tick(&mut self, pool_id: [u8; 32], tick: i32) {
+        let mut tick_info = self.ticks.get(&(pool_id, tick)).unwrap_or_default();
+        tick_info.clear();
+        self.ticks.set(&(pool_id, tick), tick_info);
+    }
+
+    /// Cross an initialized tick during a swap, flipping its outside accumulators
+    ///
+    /// # Returns
+    /// The liquidity_net of the crossed tick
+    #[allow(clippy::too_many_arguments)]
+    fn _cross_tick(
+        &mut self,
+        pool_id: [u8; 32],
+        tick: i32,
+        fee_growth_global_0_x128: U256,
+        fee_growth_global_1_x128: U256,
+        seconds_per_liquidity_cumulative_x128: U256,
+        tick_cumulative: i64,
+        block_timestamp: u32,
+    ) -> i128 {
+        let mut tick_info = self.ticks.get(&(pool_id, tick)).unwrap_or_default();
+        let liquidity_net = tick_info.cross(
+            fee_growth_global_0_x128,
+            fee_growth_global_1_x128,
+            seconds_per_liquidity_cumulative_x128,
+            tick_cumulative,
+            block_timestamp,
+        );
+        self.ticks.set(&(pool_id, tick), tick_info);
+        liquidity_net
     }
 
     /// Flip a tick's bit in the bitmap to mark it as initialized/uninitialized
-    fn _flip_tick_in_bitmap(&mut self, pool_id: [u8; 32], tick: i32) {
-        // Calculate word position and bit position within the word
-        // Each word (U256) can store 256 ticks
-        let word_pos = tick >> 8; // Divide by 256 (stays as i32 for ToBytes support)
-        // Fix for negative ticks: ensure bit_pos is always 0-255
-        let bit_pos = ((tick % 256 + 256) % 256) as u8;
-
-        // Get current word (or zero if doesn't exist)
+    ///
+    /// Ticks are stored compressed by the pool's tick spacing, 256 per word
+    fn _flip_tick_in_bitmap(&mut self, pool_id: [u8; 32], tick: i32, tick_spacing: i32) {
+        let (word_pos, bit_pos) = TickBitmap::position(tick / tick_spacing);
+
         let mut word = self.tick_bitmaps
             .get(&(pool_id, word_pos))
             .unwrap_or(U256::zero());
-
-        // Flip the bit at bit_pos
-        let mask = U256::one() << bit_pos;
-        word ^= mask;
-
-        // Store updated word
+        word ^= U256::one() << bit_pos;
         self.tick_bitmaps.set(&(pool_id, word_pos), word);
     }
 
-    /// Find the next initialized tick in the bitmap
+    /// Initialized ticks of one bitmap word with their liquidity, in ascending order
+    fn _populated_ticks_in_word(&self, pool_id: [u8; 32], tick_spacing: i32, word: i32) -> Vec<PopulatedTick> {
+        let bitmap = self.tick_bitmaps.get(&(pool_id, word)).unwrap_or(U256::zero());
+        (0..256)
+            .filter(|bit| bitmap.bit(*bit))
+            .map(|bit| {
+                let tick = ((word << 8) + bit as i32) * tick_spacing;
+                let tick_info = self.ticks.get(&(pool_id, tick)).unwrap_or_default();
+                PopulatedTick {
+                    tick,
+                    liquidity_net: tick_info.liquidity_net,
+                    liquidity_gross: tick_info.liquidity_gross,
+                }
+            })
+            .collect()
+    }
+
+    /// Find the next initialized tick within one bitmap word, see
+    /// `TickBitmap::next_initialized_tick_within_one_word`
     ///
     /// # Arguments
     /// * `pool_id` - The pool identifier
-    /// * `current_tick` - The current tick
-    /// * `search_down` - If true, search for ticks <= current_tick; if false, search for ticks > current_tick
+    /// * `tick` - The current tick
+    /// * `tick_spacing` - The pool's tick spacing
+    /// * `lte` - If true, search for ticks <= tick; if false, search for ticks > tick
     ///
     /// # Returns
-    /// * `next_tick` - The next initialized tick (or boundary tick if none found)
+    /// * `next_tick` - The next initialized tick, or the last tick of the searched word if none found
     /// * `initialized` - Whether an initialized tick was found
     fn _find_next_initialized_tick(
         &self,
         pool_id: [u8; 32],
-        current_tick: i32,
-        search_down: bool,
+        tick: i32,
+        tick_spacing: i32,
+        lte: bool,
     ) -> (i32, bool) {
-        // Get the word position and bit position
-        let word_pos = current_tick >> 8; // Divide by 256
-        let bit_pos = ((current_tick % 256 + 256) % 256) as u8;
-
-        // Get the bitmap word
+        let compressed = TickBitmap::compress(tick, tick_spacing);
+        let (word_pos, _) = TickBitmap::position(if lte { compressed } else { compressed + 1 });
         let word = self.tick_bitmaps
             .get(&(pool_id, word_pos))
             .unwrap_or(U256::zero());
 
-        if search_down {
-            // Search for initialized ticks <= current_tick
-            // Create a mask for bits at or below the current position
-            let mask = if bit_pos == 255 {
-                U256::MAX
-            } else {
-                (U256::one() << (bit_pos + 1)) - U256::one()
-            };
-            let masked = word & mask;
-
-            if !masked.is_zero() {
-                // Found an initialized tick in this word
-                let next_bit = Self::_most_significant_bit(masked);
-                let next_tick = (word_pos << 8) | (next_bit as i32);
-                (next_tick, true)
-            } else {
-                // No initialized tick in this word, return the start of this word
-                (word_pos << 8, false)
-            }
-        } else {
-            // Search for initialized ticks > current_tick
-            // Create a mask for bits above the current position
-            let mask = !(((U256::one() << (bit_pos + 1)) - U256::one()));
-            let masked = word & mask;
-
-            if !masked.is_zero() {
-                // Found an initialized tick in this word
-                let next_bit = Self::_least_significant_bit(masked);
-                let next_tick = (word_pos << 8) | (next_bit as i32);
-                (next_tick, true)
-            } else {
-                // No initialized tick in this word, return the end of this word
-                ((word_pos << 8) + 255, false)
-            }
-        }
-    }
-
-    /// Find the most significant bit (MSB) in a U256
-    fn _most_significant_bit(x: U256) -> u8 {
-        if x.is_zero() {
-            return 0;
-        }
-
-        let mut r = 0u8;
-        let mut x = x;
-
-        if x >= (U256::one() << 128) {
-            x >>= 128;
-            r += 128;
-        }
-        if x >= (U256::one() << 64) {
-            x >>= 64;
-            r += 64;
-        }
-        if x >= (U256::one() << 32) {
-            x >>= 32;
-            r += 32;
-        }
-        if x >= (U256::one() << 16) {
-            x >>= 16;
-            r += 16;
-        }
-        if x >= (U256::one() << 8) {
-            x >>= 8;
-            r += 8;
-        }
-        if x >= (U256::one() << 4) {
-            x >>= 4;
-            r += 4;
-        }
-        if x >= (U256::one() << 2) {
-            x >>= 2;
-            r += 2;
-        }
-        if x >= (U256::one() << 1) {
-            r += 1;
-        }
-
-        r
-    }
-
-    /// Find the least significant bit (LSB) in a U256
-    fn _least_significant_bit(x: U256) -> u8 {
-        if x.is_zero() {
-            return 0;
-        }
-
-        let mut r = 255u8;
-        let mut x = x;
-
-        let u128_max_as_u256 = (U256::one() << 128) - U256::one();
-        if x & u128_max_as_u256 > U256::zero() {
-            r -= 128;
-        } else {
-            x >>= 128;
-        }
-
-        if x & U256::from(u64::MAX) > U256::zero() {
-            r -= 64;
-        } else {
-            x >>= 64;
-        }
-
-        if x & U256::from(u32::MAX) > U256::zero() {
-            r -= 32;
-        } else {
-            x >>= 32;
-        }
-
-        if x & U256::from(u16::MAX) > U256::zero() {
-            r -= 16;
-        } else {
-            x >>= 16;
-        }
-
-        if x & U256::from(u8::MAX) > U256::zero() {
-            r -= 8;
-        } else {
-            x >>= 8;
-        }
-
-        if x & U256::from(0xFu8) > U256::zero() {
-            r -= 4;
-        } else {
-            x >>= 4;
-        }
-
-        if x & U256::from(0x3u8) > U256::zero() {
-            r -= 2;
-        } else {
-            x >>= 2;
-        }
-
-        if x & U256::from(0x1u8) > U256::zero() {
-            r -= 1;
-        }
-
-        r
+        TickBitmap::next_initialized_tick_in_word(word, compressed, tick_spacing, lte)
     }
 
     /// Write a new observation to the oracle
@@ -983,7 +1360,7 @@ impl UnifiedDex {
         tick: i32,
         liquidity: U128,
     ) -> Slot0 {
-        let block_timestamp = self.env().get_block_time();
+        let block_timestamp = self._block_timestamp();
 
         // Get the last observation
         let last_observation = self.observations
@@ -991,14 +1368,14 @@ impl UnifiedDex {
             .unwrap_or_default();
 
         // Only write if enough time has passed (prevent same-block manipulation)
-        if last_observation.block_timestamp >= block_timestamp as u32 {
+        if last_observation.block_timestamp >= block_timestamp {
             return slot0; // Same block, don't update
         }
 
         // Transform the observation
         let new_observation = Observation::transform(
             &last_observation,
-            block_timestamp as u32,
+            block_timestamp,
             tick,
             liquidity,
         );
@@ -1018,126 +1395,587 @@ impl UnifiedDex {
         slot0
     }
 
-    /// Observe TWAP price over a period
-    ///
-    /// # Arguments
-    /// * `pool_id` - The pool identifier
-    /// * `slot0` - The current slot0 state
-    /// * `seconds_ago` - How many seconds ago to observe (0 = current)
+    /// Oracle accumulators as of `block_timestamp`, extrapolated from the latest observation
     ///
     /// # Returns
-    /// The tick cumulative at that time
-    fn _observe(
+    /// * `tick_cumulative` - The tick accumulator
+    /// * `seconds_per_liquidity_cumulative_x128` - The seconds per liquidity accumulator
+    fn _observe_current(
         &self,
         pool_id: [u8; 32],
-        slot0: &Slot0,
-        seconds_ago: u32,
-    ) -> Option<i64> {
-        let current_time = self.env().get_block_time() as u32;
+        pool_data: &PoolData,
+        block_timestamp: u32,
+    ) -> (i64, U256) {
+        let last = self.observations
+            .get(&(pool_id, pool_data.slot0.observation_index))
+            .unwrap_or_default();
 
-        if seconds_ago == 0 {
-            // Current observation
-            let obs = self.observations.get(&(pool_id, slot0.observation_index))?;
-            return Some(obs.tick_cumulative);
+        if last.block_timestamp == block_timestamp {
+            return (last.tick_cumulative, last.seconds_per_liquidity_cumulative_x128);
         }
 
-        let target_time = current_time.checked_sub(seconds_ago)?;
+        let observation = Observation::transform(
+            &last,
+            block_timestamp,
+            pool_data.slot0.tick,
+            pool_data.liquidity,
+        );
+        (observation.tick_cumulative, observation.seconds_per_liquidity_cumulative_x128)
+    }
 
-        // Find the observation closest to target_time
-        // For simplicity, we look at the current and previous observation
-        let current_obs = self.observations.get(&(pool_id, slot0.observation_index))?;
+    /// Oracle clock: the block time in seconds, truncated to 32 bits
+    fn _block_timestamp(&self) -> u32 {
+        self.env().get_block_time_secs() as u32
+    }
 
-        // If we have exactly the time we want
-        if current_obs.block_timestamp == target_time {
-            return Some(current_obs.tick_cumulative);
+    /// Comparator for 32-bit timestamps that may have wrapped around since `a` and `b`
+    ///
+    /// # Returns
+    /// Whether `a` is chronologically <= `b`, assuming both are at or before `time`
+    fn _lte(time: u32, a: u32, b: u32) -> bool {
+        // If there hasn't been overflow, no need to adjust
+        if a <= time && b <= time {
+            return a <= b;
         }
 
-        // Get previous observation
-        let prev_index = if slot0.observation_index == 0 {
-            slot0.observation_cardinality - 1
-        } else {
-            slot0.observation_index - 1
-        };
+        let a_adjusted = if a > time { a as u64 } else { a as u64 + (1u64 << 32) };
+        let b_adjusted = if b > time { b as u64 } else { b as u64 + (1u64 << 32) };
+        a_adjusted <= b_adjusted
+    }
+
+    /// Binary search for the observations surrounding `target`
+    /// The result may be the same observation, or adjacent observations
+    ///
+    /// The answer must be contained in the array, used when the target is located within the
+    /// stored observation boundaries: older than the most recent observation and younger than,
+    /// or the same age as, the oldest observation
+    fn _binary_search(
+        &self,
+        pool_id: [u8; 32],
+        time: u32,
+        target: u32,
+        index: u32,
+        cardinality: u32,
+    ) -> (Observation, Observation) {
+        let mut l = ((index + 1) % cardinality) as u64; // oldest observation
+        let mut r = l + cardinality as u64 - 1; // newest observation
+
+        loop {
+            let i = (l + r) / 2;
+
+            let before_or_at = self.observations
+                .get(&(pool_id, (i % cardinality as u64) as u32))
+                .unwrap_or_default();
+
+            // We've landed on an uninitialized observation, keep searching higher (more recently)
+            if !before_or_at.initialized {
+                l = i + 1;
+                continue;
+            }
 
-        let prev_obs = self.observations.get(&(pool_id, prev_index))?;
+            let at_or_after = self.observations
+                .get(&(pool_id, ((i + 1) % cardinality as u64) as u32))
+                .unwrap_or_default();
 
-        // Interpolate between observations
-        if target_time >= prev_obs.block_timestamp && target_time <= current_obs.block_timestamp {
-            let delta = target_time - prev_obs.block_timestamp;
-            let total_delta = current_obs.block_timestamp - prev_obs.block_timestamp;
+            let target_at_or_after = Self::_lte(time, before_or_at.block_timestamp, target);
 
-            if total_delta == 0 {
-                return Some(current_obs.tick_cumulative);
+            // Check if we've found the answer
+            if target_at_or_after && Self::_lte(time, target, at_or_after.block_timestamp) {
+                return (before_or_at, at_or_after);
             }
 
-            let tick_delta = current_obs.tick_cumulative - prev_obs.tick_cumulative;
-            let interpolated = prev_obs.tick_cumulative + (tick_delta * delta as i64 / total_delta as i64);
+            if !target_at_or_after {
+                r = i - 1;
+            } else {
+                l = i + 1;
+            }
+        }
+    }
 
-            Some(interpolated)
-        } else {
-            None // Time is outside available observation window
+    /// Fetches the observations before or at, and at or after, a given target
+    ///
+    /// # Returns
+    /// The surrounding observations, or `None` if `target` is older than the oldest observation
+    fn _get_surrounding_observations(
+        &self,
+        pool_id: [u8; 32],
+        pool_data: &PoolData,
+        time: u32,
+        target: u32,
+    ) -> Option<(Observation, Observation)> {
+        let index = pool_data.slot0.observation_index;
+        let cardinality = pool_data.slot0.observation_cardinality;
+
+        // Optimistically set before to the newest observation
+        let before_or_at = self.observations.get(&(pool_id, index)).unwrap_or_default();
+
+        // If the target is chronologically at or after the newest observation, we can early return
+        if Self::_lte(time, before_or_at.block_timestamp, target) {
+            if before_or_at.block_timestamp == target {
+                // If newest observation equals target, we're in the same block, so we can ignore at_or_after
+                return Some((before_or_at.clone(), before_or_at));
+            }
+            // Otherwise, we need to transform
+            let at_or_after = Observation::transform(
+                &before_or_at,
+                target,
+                pool_data.slot0.tick,
+                pool_data.liquidity,
+            );
+            return Some((before_or_at, at_or_after));
+        }
+
+        // Now, set before to the oldest observation
+        let mut oldest = self.observations
+            .get(&(pool_id, (index + 1) % cardinality))
+            .unwrap_or_default();
+        if !oldest.initialized {
+            oldest = self.observations.get(&(pool_id, 0)).unwrap_or_default();
+        }
+
+        // Ensure that the target is chronologically at or after the oldest observation
+        if !Self::_lte(time, oldest.block_timestamp, target) {
+            return None;
         }
+
+        // If we've reached this point, we have to binary search
+        Some(self._binary_search(pool_id, time, target, index, cardinality))
     }
 
-    fn compute_pool_id(token0: Address, token1: Address, fee: u32) -> [u8; 32] {
-        use odra::casper_types::bytesrepr::ToBytes;
+    /// Oracle accumulators as of `seconds_ago` seconds before `time`, interpolated between
+    /// the surrounding observations or extrapolated from the newest one
+    ///
+    /// # Returns
+    /// The tick cumulative and seconds per liquidity cumulative, or `None` if the
+    /// requested time is older than the oldest observation
+    fn _observe_single(
+        &self,
+        pool_id: [u8; 32],
+        pool_data: &PoolData,
+        time: u32,
+        seconds_ago: u32,
+    ) -> Option<(i64, U256)> {
+        if seconds_ago == 0 {
+            return Some(self._observe_current(pool_id, pool_data, time));
+        }
+
+        let target = time.wrapping_sub(seconds_ago);
+        let (before_or_at, at_or_after) =
+            self._get_surrounding_observations(pool_id, pool_data, time, target)?;
 
-        let mut key_bytes = Vec::new();
-        key_bytes.extend_from_slice(&token0.to_bytes().unwrap());
-        key_bytes.extend_from_slice(&token1.to_bytes().unwrap());
-        key_bytes.extend_from_slice(&fee.to_le_bytes());
+        if target == before_or_at.block_timestamp {
+            // We're at the left boundary
+            Some((before_or_at.tick_cumulative, before_or_at.seconds_per_liquidity_cumulative_x128))
+        } else if target == at_or_after.block_timestamp {
+            // We're at the right boundary
+            Some((at_or_after.tick_cumulative, at_or_after.seconds_per_liquidity_cumulative_x128))
+        } else {
+            // We're in the middle
+            let observation_time_delta = at_or_after.block_timestamp.wrapping_sub(before_or_at.block_timestamp);
+            let target_delta = target.wrapping_sub(before_or_at.block_timestamp);
+            let tick_cumulative = before_or_at.tick_cumulative
+                + (at_or_after.tick_cumulative - before_or_at.tick_cumulative) / observation_time_delta as i64
+                    * target_delta as i64;
+            let seconds_per_liquidity_cumulative_x128 = before_or_at.seconds_per_liquidity_cumulative_x128
+                + FullMath::mul_div(
+                    at_or_after.seconds_per_liquidity_cumulative_x128
+                        .overflowing_sub(before_or_at.seconds_per_liquidity_cumulative_x128).0,
+                    U256::from(target_delta),
+                    U256::from(observation_time_delta),
+                ).unwrap_or_revert(self);
+            Some((tick_cumulative, seconds_per_liquidity_cumulative_x128))
+        }
+    }
 
-        // Use a simple but collision-resistant hash combining all bytes
-        // In production, consider using a cryptographic hash library
-        let mut hash = [0u8; 32];
-        let len = key_bytes.len();
-        for (i, byte) in key_bytes.iter().enumerate() {
-            // Better mixing than simple XOR
-            hash[i % 32] = hash[i % 32].wrapping_add(*byte).wrapping_mul(31);
-            hash[(i * 7) % 32] ^= byte.rotate_left((i % 8) as u32);
+    /// Acquire a pool's reentrancy lock, reverting if the pool is already mid-update
+    fn _lock(&mut self, pool_key: (Address, Address, u32)) {
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        if !pool_data.slot0.unlocked {
+            self.env().revert(Error::PoolLocked);
         }
-        // Final mixing pass
-        for i in 0..32 {
-            hash[i] ^= (len as u8).wrapping_mul((i + 1) as u8);
+        pool_data.slot0.unlocked = false;
+        self.pools.set(&pool_key, pool_data);
+    }
+
+    /// Release a pool's reentrancy lock
+    fn _unlock(&mut self, pool_key: (Address, Address, u32)) {
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        pool_data.slot0.unlocked = true;
+        self.pools.set(&pool_key, pool_data);
+    }
+
+    /// Add `amount` to a 128-bit balance of owed tokens, reverting if the sum does not fit
+    fn _add_owed(&self, owed: U128, amount: U256) -> U128 {
+        if amount > U256::from(u128::MAX) {
+            self.env().revert(Error::FeesOverflow);
         }
-        hash
+        owed.checked_add(U128::from(amount.as_u128()))
+            .unwrap_or_revert_with(self, Error::FeesOverflow)
+    }
+
+    /// Return the sequence number for the next event emitted by a pool and advance the counter
+    fn _next_event_sequence(&mut self, pool_id: [u8; 32]) -> u64 {
+        let sequence = self.event_sequences.get_or_default(&pool_id);
+        self.event_sequences.set(&pool_id, sequence + 1);
+        sequence
+    }
+
+    /// blake2b-256 over the bytesrepr encoding of `(token0, token1, fee)`, see `get_pool_id`
+    fn compute_pool_id(token0: Address, token1: Address, fee: u32) -> [u8; 32] {
+        Digest::hash((token0, token1, fee).to_bytes().unwrap()).value()
     }
 
+    /// blake2b-256 over the bytesrepr encoding of `(owner, tick_lower, tick_upper)`, see `get_position_key`
     fn compute_position_key(owner: Address, tick_lower: i32, tick_upper: i32) -> [u8; 32] {
-        use odra::casper_types::bytesrepr::ToBytes;
+        PositionKey::new(owner, tick_lower, tick_upper).compute_key()
+    }
 
-        let mut key_bytes = Vec::new();
-        key_bytes.extend_from_slice(&owner.to_bytes().unwrap());
-        key_bytes.extend_from_slice(&tick_lower.to_le_bytes());
-        key_bytes.extend_from_slice(&tick_upper.to_le_bytes());
+    /// Sort a token pair into pool order, returning whether it was given as `(token1, token0)`
+    fn _sort_tokens(token_a: Address, token_b: Address) -> (Address, Address, bool) {
+        if token_a < token_b {
+            (token_a, token_b, false)
+        } else {
+            (token_b, token_a, true)
+        }
+    }
 
-        // Use a simple but collision-resistant hash combining all bytes
-        let mut hash = [0u8; 32];
-        let len = key_bytes.len();
-        for (i, byte) in key_bytes.iter().enumerate() {
-            // Better mixing than simple XOR
-            hash[i % 32] = hash[i % 32].wrapping_add(*byte).wrapping_mul(31);
-            hash[(i * 7) % 32] ^= byte.rotate_left((i % 8) as u32);
+    /// Swap a pair of per-token values between pool order and the caller's order
+    fn _order<T>(values: (T, T), flipped: bool) -> (T, T) {
+        if flipped {
+            (values.1, values.0)
+        } else {
+            values
         }
-        // Final mixing pass
-        for i in 0..32 {
-            hash[i] ^= (len as u8).wrapping_mul((i + 1) as u8);
+    }
+
+    /// Map a tick range between pool order and the caller's order; inverting the price negates ticks
+    fn _order_ticks(tick_lower: i32, tick_upper: i32, flipped: bool) -> (i32, i32) {
+        if flipped {
+            (-tick_upper, -tick_lower)
+        } else {
+            (tick_lower, tick_upper)
         }
-        hash
+    }
+
+    /// Invert a Q64.96 sqrt price, i.e. express a token1/token0 price as token0/token1
+    fn _invert_sqrt_price(sqrt_price_x96: U256) -> Result<U256, Error> {
+        FullMath::mul_div(U256::one() << 96, U256::one() << 96, sqrt_price_x96)
+    }
+
+    /// Identifier of the pool for a token pair and fee tier, in either token order
+    ///
+    /// The id is the blake2b-256 digest of the bytesrepr-encoded `(token0, token1, fee)`
+    /// tuple with the tokens sorted. It keys all per-pool storage and is carried by every
+    /// pool event as `pool_id`.
+    pub fn get_pool_id(&self, token_a: Address, token_b: Address, fee: u32) -> [u8; 32] {
+        let (token0, token1, _) = Self::_sort_tokens(token_a, token_b);
+        Self::compute_pool_id(token0, token1, fee)
+    }
+
+    /// Key of a position within its pool
+    ///
+    /// The key is the blake2b-256 digest of the bytesrepr-encoded `(owner, tick_lower, tick_upper)`
+    /// tuple, identical to `PositionKey::compute_key`.
+    pub fn get_position_key(&self, owner: Address, tick_lower: i32, tick_upper: i32) -> [u8; 32] {
+        Self::compute_position_key(owner, tick_lower, tick_upper)
     }
 
     // Getters
     pub fn get_pool(&self, token0: Address, token1: Address, fee: u32) -> Option<PoolData> {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
         self.pools.get(&(token0, token1, fee))
     }
 
-    pub fn enable_fee_amount(&mut self, fee: u32, tick_spacing: i32) {
-        self.ownable.assert_owner(&self.env().caller());
-        assert!(fee < 1000000, "Fee too high");
-        assert!(tick_spacing > 0 && tick_spacing < 16384, "Invalid tick spacing");
-        assert!(self.fee_amount_tick_spacing.get(&fee).is_none(), "Fee already enabled");
+    /// Tokens the DEX holds for a pool, in the caller's order
+    ///
+    /// Covers the liquidity, uncollected fees and tokens owed to positions, which the pool's
+    /// swaps, collects and flash loans pay out of.
+    pub fn get_pool_reserves(&self, token0: Address, token1: Address, fee: u32) -> (U256, U256) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        Self::_order(
+            (self.reserves.pool_reserve(pool_id, token0), self.reserves.pool_reserve(pool_id, token1)),
+            flipped,
+        )
+    }
+
+    /// Number of pools created so far
+    pub fn all_pools_length(&self) -> u32 {
+        self.all_pools.len()
+    }
+
+    /// Pool at `index` in creation order, from 0 to `all_pools_length() - 1`
+    pub fn pool_at(&self, index: u32) -> Option<PoolData> {
+        let pool_key = self.all_pools.get(index)?;
+        self.pools.get(&pool_key)
+    }
+
+    /// Pool identified by its `compute_pool_id` hash
+    pub fn get_pool_by_id(&self, pool_id: [u8; 32]) -> Option<PoolData> {
+        let pool_key = self.pool_keys.get(&pool_id)?;
+        self.pools.get(&pool_key)
+    }
+
+    /// Every existing pool for a token pair, one per enabled fee tier, in either token order
+    pub fn get_pools_for_pair(&self, token_a: Address, token_b: Address) -> Vec<PoolData> {
+        let (token0, token1, _) = Self::_sort_tokens(token_a, token_b);
+        self.fee_amounts
+            .iter()
+            .filter_map(|fee| self.pools.get(&(token0, token1, fee)))
+            .collect()
+    }
+
+    /// Fee tiers that pools can be created with
+    pub fn get_fee_amounts(&self) -> Vec<u32> {
+        self.fee_amounts.iter().collect()
+    }
+
+    /// Sequence number the next event of a pool will carry (also the number of events emitted so far)
+    pub fn get_event_sequence(&self, token0: Address, token1: Address, fee: u32) -> u64 {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        self.event_sequences.get_or_default(&Self::compute_pool_id(token0, token1, fee))
+    }
+
+    /// Account that created a pool
+    pub fn get_pool_creator(&self, token0: Address, token1: Address, fee: u32) -> Option<Address> {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        self.pool_creators.get(&Self::compute_pool_id(token0, token1, fee))
+    }
+
+    /// Whether `initialize_pool` is limited to the pool creator and the owner
+    pub fn is_initialization_restricted(&self) -> bool {
+        self.initialization_restricted.get_or_default()
+    }
+
+    /// Limit `initialize_pool` to the pool creator and the owner, and `create_pool` to the owner,
+    /// so nobody else can set the starting price of a freshly created pool (owner only)
+    pub fn set_initialization_restricted(&mut self, restricted: bool) {
+        self.roles.assert_owner();
+        self.initialization_restricted.set(restricted);
+    }
+
+    delegate! {
+        to self.roles {
+            /// Whether `address` holds `role` (see `crate::access` for the roles)
+            fn has_role(&self, role: Role, address: Address) -> bool;
+            /// Account that grants and revokes roles
+            fn get_owner(&self) -> Address;
+            /// Account nominated by `transfer_ownership` that has not accepted yet, if any
+            fn get_pending_owner(&self) -> Option<Address>;
+            /// Grant `role` to `address` (owner only)
+            fn grant_role(&mut self, role: Role, address: Address);
+            /// Revoke `role` from `address` (owner only)
+            fn revoke_role(&mut self, role: Role, address: Address);
+            /// Give up `role` held by the caller
+            fn renounce_role(&mut self, role: Role);
+            /// Nominate `new_owner`, who takes over once they call `accept_ownership` (owner only)
+            fn transfer_ownership(&mut self, new_owner: Address);
+            /// Take over ownership and the old owner's roles (pending owner only)
+            fn accept_ownership(&mut self);
+        }
+    }
+
+    /// `PAUSE_*` flags paused in every pool
+    pub fn get_paused_actions(&self) -> u8 {
+        self.guard.paused_actions()
+    }
+
+    /// `PAUSE_*` flags paused in a single pool, on top of the global ones
+    pub fn get_pool_paused_actions(&self, token0: Address, token1: Address, fee: u32) -> u8 {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        self.guard.pool_paused_actions(Self::compute_pool_id(token0, token1, fee))
+    }
+
+    /// Whether any of the `PAUSE_*` flags in `actions` is paused for a pool, globally or for that pool
+    pub fn is_paused(&self, token0: Address, token1: Address, fee: u32, actions: u8) -> bool {
+        let paused = self.get_paused_actions() | self.get_pool_paused_actions(token0, token1, fee);
+        paused & actions != 0
+    }
+
+    /// Replace the actions paused in every pool (pauser role)
+    ///
+    /// # Arguments
+    /// * `actions` - `PAUSE_*` flags to pause; every other action is unpaused
+    pub fn set_paused_actions(&mut self, actions: u8) {
+        self.roles.assert_role(PAUSER_ROLE);
+        if actions & !PAUSE_ALL != 0 {
+            self.env().revert(Error::InvalidPauseActions);
+        }
+
+        let paused_actions_old = self.get_paused_actions();
+        self.guard.set_paused_actions(actions);
+
+        self.env().emit_event(GlobalPauseSet {
+            sender: self.env().caller(),
+            paused_actions_old,
+            paused_actions_new: actions,
+        });
+    }
+
+    /// Replace the actions paused in one pool (pauser role)
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `actions` - `PAUSE_*` flags to pause; every other action is unpaused unless paused globally
+    pub fn set_pool_paused_actions(&mut self, token0: Address, token1: Address, fee: u32, actions: u8) {
+        self.roles.assert_role(PAUSER_ROLE);
+        if actions & !PAUSE_ALL != 0 {
+            self.env().revert(Error::InvalidPauseActions);
+        }
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        if self.pools.get(&(token0, token1, fee)).is_none() {
+            self.env().revert(Error::PoolDoesNotExist);
+        }
+
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let paused_actions_old = self.guard.pool_paused_actions(pool_id);
+        self.guard.set_pool_paused_actions(pool_id, actions);
+
+        self.env().emit_event(PoolPauseSet {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
+            sender: self.env().caller(),
+            paused_actions_old,
+            paused_actions_new: actions,
+        });
+    }
+
+    /// Enable a new fee tier with its tick spacing (fee-tier admin role)
+    pub fn enable_fee_amount(&mut self, fee: u32, tick_spacing: i32) {
+        self.roles.assert_role(FEE_TIER_ADMIN_ROLE);
+        if fee >= 1000000 {
+            self.env().revert(Error::InvalidFee);
+        }
+        if tick_spacing <= 0 || tick_spacing >= 16384 {
+            self.env().revert(Error::InvalidTickSpacing);
+        }
+        if self.fee_amount_tick_spacing.get(&fee).is_some() {
+            self.env().revert(Error::FeeAlreadyEnabled);
+        }
 
         self.fee_amount_tick_spacing.set(&fee, tick_spacing);
+        self.fee_amounts.push(fee);
+    }
+
+    /// Set the protocol's share of swap fees for a pool (protocol-fee collector role)
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `fee_protocol_0` - New protocol fee denominator for token0 (0 = off, otherwise 4-10 for 1/N of the fee)
+    /// * `fee_protocol_1` - New protocol fee denominator for token1 (0 = off, otherwise 4-10 for 1/N of the fee)
+    pub fn set_fee_protocol(
+        &mut self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        fee_protocol_0: u8,
+        fee_protocol_1: u8,
+    ) {
+        self.roles.assert_role(PROTOCOL_FEE_COLLECTOR_ROLE);
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let (fee_protocol_0, fee_protocol_1) = Self::_order((fee_protocol_0, fee_protocol_1), flipped);
+        if !((fee_protocol_0 == 0 || (4..=10).contains(&fee_protocol_0))
+            && (fee_protocol_1 == 0 || (4..=10).contains(&fee_protocol_1)))
+        {
+            self.env().revert(Error::InvalidFeeProtocol);
+        }
+
+        let pool_key = (token0, token1, fee);
+        self._lock(pool_key);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+
+        let fee_protocol_old = pool_data.slot0.fee_protocol;
+        pool_data.slot0.fee_protocol = fee_protocol_0 + (fee_protocol_1 << 4);
+        self.pools.set(&pool_key, pool_data);
+
+        self.env().emit_event(SetFeeProtocol {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
+            fee_protocol_0_old: fee_protocol_old % 16,
+            fee_protocol_1_old: fee_protocol_old >> 4,
+            fee_protocol_0_new: fee_protocol_0,
+            fee_protocol_1_new: fee_protocol_1,
+        });
+
+        self._unlock(pool_key);
+    }
+
+    /// Collect the protocol fees accrued to a pool (protocol-fee collector role)
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `recipient` - Address receiving the protocol fees
+    /// * `amount0_requested` - Maximum amount of token0 to collect
+    /// * `amount1_requested` - Maximum amount of token1 to collect
+    ///
+    /// # Returns
+    /// The amounts of token0 and token1 collected
+    pub fn collect_protocol(
+        &mut self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        recipient: Address,
+        amount0_requested: U128,
+        amount1_requested: U128,
+    ) -> (U128, U128) {
+        let caller = self.env().caller();
+        self.roles.assert_role(PROTOCOL_FEE_COLLECTOR_ROLE);
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let (amount0_requested, amount1_requested) = Self::_order((amount0_requested, amount1_requested), flipped);
+
+        let pool_key = (token0, token1, fee);
+        self._lock(pool_key);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+
+        let amount0 = amount0_requested.min(pool_data.protocol_fees_token0);
+        let amount1 = amount1_requested.min(pool_data.protocol_fees_token1);
+
+        pool_data.protocol_fees_token0 -= amount0;
+        pool_data.protocol_fees_token1 -= amount1;
+        self.pools.set(&pool_key, pool_data);
+
+        if !amount0.is_zero() {
+            let mut token0_contract = Erc20ContractRef::new(self.env(), token0);
+            self.reserves.debit(pool_id, token0, U256::from(amount0.as_u128()));
+            token0_contract.transfer(&recipient, &U256::from(amount0.as_u128()));
+        }
+
+        if !amount1.is_zero() {
+            let mut token1_contract = Erc20ContractRef::new(self.env(), token1);
+            self.reserves.debit(pool_id, token1, U256::from(amount1.as_u128()));
+            token1_contract.transfer(&recipient, &U256::from(amount1.as_u128()));
+        }
+
+        self.env().emit_event(CollectProtocol {
+            token0,
+            token1,
+            fee,
+            pool_id,
+            sequence: self._next_event_sequence(pool_id),
+            sender: caller,
+            recipient,
+            amount0,
+            amount1,
+        });
+
+        self._unlock(pool_key);
+
+        Self::_order((amount0, amount1), flipped)
     }
 
     // ========== FRONTEND INTEGRATION FUNCTIONS ==========
@@ -1174,7 +2012,7 @@ impl UnifiedDex {
             pool_data.liquidity,
             amount_in_after_fee,
             zero_for_one,
-        );
+        ).ok()?;
 
         let amount_out = if zero_for_one {
             crate::math::SqrtPriceMath::get_amount1_delta(
@@ -1182,17 +2020,17 @@ impl UnifiedDex {
                 pool_data.slot0.sqrt_price_x96,
                 pool_data.liquidity,
                 false,
-            )
+            ).ok()?
         } else {
             crate::math::SqrtPriceMath::get_amount0_delta(
                 pool_data.slot0.sqrt_price_x96,
                 sqrt_price_after,
                 pool_data.liquidity,
                 false,
-            )
+            ).ok()?
         };
 
-        let tick_after = crate::math::TickMath::get_tick_at_sqrt_ratio(sqrt_price_after);
+        let tick_after = crate::math::TickMath::get_tick_at_sqrt_ratio(sqrt_price_after).ok()?;
 
         Some(QuoteResult {
             amount_out,
@@ -1202,8 +2040,9 @@ impl UnifiedDex {
         })
     }
 
-    /// Get current price in human-readable format (token1/token0)
+    /// Get current price in human-readable format: the second token per unit of the first
     pub fn get_price(&self, token0: Address, token1: Address, fee: u32) -> Option<U256> {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
         let pool_key = (token0, token1, fee);
         let pool_data = self.pools.get(&pool_key)?;
 
@@ -1211,15 +2050,72 @@ impl UnifiedDex {
             return None;
         }
 
-        // price = (sqrtPriceX96 / 2^96) ^ 2
+        // price = (sqrtPriceX96 / 2^96) ^ 2, or its inverse in the reverse token order
         let sqrt_price = pool_data.slot0.sqrt_price_x96;
-        let q96 = U256::from(1u128 << 96);
-        let price = (sqrt_price * sqrt_price) / (q96 * q96);
+        let price = if flipped {
+            FullMath::mul_div(U256::one() << 192, U256::one(), sqrt_price).ok()? / sqrt_price
+        } else {
+            FullMath::mul_div(sqrt_price, sqrt_price, U256::one() << 192).ok()?
+        };
 
         Some(price)
     }
 
+    /// State of an initialized tick, or `None` if no position references it
+    pub fn get_tick(&self, token0: Address, token1: Address, fee: u32, tick: i32) -> Option<Tick> {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        self.ticks.get(&(pool_id, tick)).filter(|tick_info| tick_info.initialized)
+    }
+
+    /// Initialized ticks in one tick bitmap word, in ascending order
+    ///
+    /// # Arguments
+    /// * `word` - The bitmap word index: ticks compressed by the pool's tick spacing, divided by 256
+    pub fn get_populated_ticks_in_word(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        word: i32,
+    ) -> Vec<PopulatedTick> {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        let pool_data = self.pools.get(&(token0, token1, fee)).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        self._populated_ticks_in_word(pool_id, pool_data.tick_spacing, word)
+    }
+
+    /// Initialized ticks between `tick_from` and `tick_to` (inclusive), in ascending order
+    ///
+    /// Walks the tick bitmap one word at a time, so wide ranges on pools with a small
+    /// tick spacing are expensive; intended for off-chain queries.
+    pub fn get_liquidity_distribution(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        tick_from: i32,
+        tick_to: i32,
+    ) -> Vec<PopulatedTick> {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
+        if tick_from > tick_to {
+            self.env().revert(Error::InvalidTickRange);
+        }
+        let pool_data = self.pools.get(&(token0, token1, fee)).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let tick_spacing = pool_data.tick_spacing;
+
+        let (word_from, _) = TickBitmap::position(TickBitmap::compress(tick_from, tick_spacing));
+        let (word_to, _) = TickBitmap::position(TickBitmap::compress(tick_to, tick_spacing));
+        (word_from..=word_to)
+            .flat_map(|word| self._populated_ticks_in_word(pool_id, tick_spacing, word))
+            .filter(|populated| populated.tick >= tick_from && populated.tick <= tick_to)
+            .collect()
+    }
+
     /// Get position details including tokens owed
+    ///
+    /// The tokens owed and fee growth fields follow the caller's token order.
     pub fn get_position_with_fees(
         &self,
         token0: Address,
@@ -1229,9 +2125,156 @@ impl UnifiedDex {
         tick_lower: i32,
         tick_upper: i32,
     ) -> Option<Position> {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let (tick_lower, tick_upper) = Self::_order_ticks(tick_lower, tick_upper, flipped);
+        let pool_data = self.pools.get(&(token0, token1, fee))?;
         let pool_id = Self::compute_pool_id(token0, token1, fee);
         let position_key = Self::compute_position_key(owner, tick_lower, tick_upper);
-        self.positions.get(&(pool_id, position_key))
+        let mut position = self.positions.get(&(pool_id, position_key))?;
+
+        // Include fees earned since the position was last checkpointed
+        let lower = self.ticks.get(&(pool_id, tick_lower)).unwrap_or_default();
+        let upper = self.ticks.get(&(pool_id, tick_upper)).unwrap_or_default();
+        let (fee_growth_inside_0_x128, fee_growth_inside_1_x128) = Tick::get_fee_growth_inside(
+            &lower,
+            &upper,
+            tick_lower,
+            tick_upper,
+            pool_data.slot0.tick,
+            pool_data.fee_growth_global_0_x128,
+            pool_data.fee_growth_global_1_x128,
+        );
+        let (tokens_owed_0, tokens_owed_1) =
+            position.get_fees_owed(fee_growth_inside_0_x128, fee_growth_inside_1_x128).unwrap_or_revert(self);
+        (position.tokens_owed_0, position.tokens_owed_1) = Self::_order((tokens_owed_0, tokens_owed_1), flipped);
+        (position.fee_growth_inside_0_last_x128, position.fee_growth_inside_1_last_x128) = Self::_order(
+            (position.fee_growth_inside_0_last_x128, position.fee_growth_inside_1_last_x128),
+            flipped,
+        );
+
+        Some(position)
+    }
+
+    /// Returns a snapshot of the tick cumulative, seconds per liquidity and seconds inside a tick range
+    ///
+    /// Snapshots must only be compared to other snapshots, taken over a period for which a position
+    /// existed. I.e., snapshots cannot be compared if a position is not held for the entire period
+    /// between when the first snapshot is taken and the second snapshot is taken.
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `tick_lower` - The lower tick of the range
+    /// * `tick_upper` - The upper tick of the range
+    ///
+    /// # Returns
+    /// * `tick_cumulative_inside` - The snapshot of the tick accumulator for the range
+    /// * `seconds_per_liquidity_inside_x128` - The snapshot of seconds per liquidity for the range
+    /// * `seconds_inside` - The snapshot of the number of seconds during which the price was in the range
+    pub fn snapshot_cumulatives_inside(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> (i64, U256, u32) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let (tick_lower, tick_upper) = Self::_order_ticks(tick_lower, tick_upper, flipped);
+        if tick_lower >= tick_upper {
+            self.env().revert(Error::InvalidTickRange);
+        }
+        if tick_lower < MIN_TICK || tick_upper > MAX_TICK {
+            self.env().revert(Error::TickOutOfBounds);
+        }
+
+        let pool_data = self.pools.get(&(token0, token1, fee)).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let lower = self.ticks.get(&(pool_id, tick_lower)).unwrap_or_default();
+        let upper = self.ticks.get(&(pool_id, tick_upper)).unwrap_or_default();
+        if !lower.initialized || !upper.initialized {
+            self.env().revert(Error::TickNotInitialized);
+        }
+
+        // The outside accumulators only have relative meaning, so differences are taken modulo their width
+        let tick_current = pool_data.slot0.tick;
+        let (tick_cumulative_inside, seconds_per_liquidity_inside_x128, seconds_inside) = if tick_current < tick_lower {
+            (
+                lower.tick_cumulative_outside.wrapping_sub(upper.tick_cumulative_outside),
+                lower.seconds_per_liquidity_outside_x128
+                    .overflowing_sub(upper.seconds_per_liquidity_outside_x128).0,
+                lower.seconds_outside.wrapping_sub(upper.seconds_outside),
+            )
+        } else if tick_current < tick_upper {
+            let time = self._block_timestamp();
+            let (tick_cumulative, seconds_per_liquidity_cumulative_x128) =
+                self._observe_current(pool_id, &pool_data, time);
+            (
+                tick_cumulative
+                    .wrapping_sub(lower.tick_cumulative_outside)
+                    .wrapping_sub(upper.tick_cumulative_outside),
+                seconds_per_liquidity_cumulative_x128
+                    .overflowing_sub(lower.seconds_per_liquidity_outside_x128).0
+                    .overflowing_sub(upper.seconds_per_liquidity_outside_x128).0,
+                time.wrapping_sub(lower.seconds_outside).wrapping_sub(upper.seconds_outside),
+            )
+        } else {
+            (
+                upper.tick_cumulative_outside.wrapping_sub(lower.tick_cumulative_outside),
+                upper.seconds_per_liquidity_outside_x128
+                    .overflowing_sub(lower.seconds_per_liquidity_outside_x128).0,
+                upper.seconds_outside.wrapping_sub(lower.seconds_outside),
+            )
+        };
+
+        // Ticks accumulate the price of token0 in token1, so the reverse order negates them
+        let tick_cumulative_inside = if flipped { tick_cumulative_inside.wrapping_neg() } else { tick_cumulative_inside };
+        (tick_cumulative_inside, seconds_per_liquidity_inside_x128, seconds_inside)
+    }
+
+    /// Returns the cumulative tick and liquidity as of each timestamp `seconds_ago` from the current block timestamp
+    ///
+    /// To get a time weighted average tick or liquidity-in-range, call this with two values, one
+    /// representing the beginning of the period and another for the end of the period. E.g., to
+    /// get the last hour time-weighted average tick, call it with `seconds_agos = [3600, 0]`.
+    /// Reverts if any requested time is older than the oldest stored observation; grow the
+    /// buffer with `increase_observation_cardinality` to look further back.
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `seconds_agos` - From how long ago each cumulative value should be returned
+    ///
+    /// # Returns
+    /// * `tick_cumulatives` - Cumulative tick values as of each `seconds_agos` from the current block timestamp
+    /// * `seconds_per_liquidity_cumulative_x128s` - Cumulative seconds per liquidity-in-range value as of each `seconds_agos`
+    pub fn observe(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        seconds_agos: Vec<u32>,
+    ) -> (Vec<i64>, Vec<U256>) {
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
+        let pool_data = self.pools.get(&(token0, token1, fee)).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
+        if pool_data.slot0.observation_cardinality == 0 {
+            self.env().revert(Error::PoolNotInitialized);
+        }
+        let pool_id = Self::compute_pool_id(token0, token1, fee);
+        let time = self._block_timestamp();
+
+        seconds_agos
+            .iter()
+            .map(|seconds_ago| {
+                let (tick_cumulative, seconds_per_liquidity_cumulative_x128) = self
+                    ._observe_single(pool_id, &pool_data, time, *seconds_ago)
+                    .unwrap_or_revert_with(self, Error::ObservationTooOld);
+                let tick_cumulative = if flipped { -tick_cumulative } else { tick_cumulative };
+                (tick_cumulative, seconds_per_liquidity_cumulative_x128)
+            })
+            .unzip()
     }
 
     /// Get TWAP (Time-Weighted Average Price) over a period
@@ -1244,7 +2287,9 @@ impl UnifiedDex {
     /// * `seconds_ago_end` - Newest time point (usually 0 for current)
     ///
     /// # Returns
-    /// Average tick over the period (can be converted to price)
+    /// Average tick over the period (can be converted to price), or `None` if the pool does not
+    /// exist, the period is older than the stored observations, or `seconds_ago_start` is not
+    /// greater than `seconds_ago_end`
     ///
     /// # Example
     /// ```ignore
@@ -1260,24 +2305,83 @@ impl UnifiedDex {
         seconds_ago_start: u32,
         seconds_ago_end: u32,
     ) -> Option<i32> {
+        if seconds_ago_start <= seconds_ago_end {
+            return None;
+        }
+
+        let (token0, token1, flipped) = Self::_sort_tokens(token0, token1);
         let pool_key = (token0, token1, fee);
         let pool_data = self.pools.get(&pool_key)?;
         let pool_id = Self::compute_pool_id(token0, token1, fee);
 
         // Get tick cumulatives at both times
-        let tick_cumulative_old = self._observe(pool_id, &pool_data.slot0, seconds_ago_start)?;
-        let tick_cumulative_new = self._observe(pool_id, &pool_data.slot0, seconds_ago_end)?;
+        let time = self._block_timestamp();
+        let (tick_cumulative_old, _) = self._observe_single(pool_id, &pool_data, time, seconds_ago_start)?;
+        let (tick_cumulative_new, _) = self._observe_single(pool_id, &pool_data, time, seconds_ago_end)?;
 
         // Calculate time-weighted average tick
         let time_delta = seconds_ago_start - seconds_ago_end;
-        if time_delta == 0 {
-            return Some(pool_data.slot0.tick);
-        }
+        let average_tick = ((tick_cumulative_new - tick_cumulative_old) / time_delta as i64) as i32;
+
+        Some(if flipped { -average_tick } else { average_tick })
+    }
 
-        let tick_delta = tick_cumulative_new - tick_cumulative_old;
-        let average_tick = tick_delta / time_delta as i64;
+    /// Get time-weighted means of tick and liquidity over the last `period` seconds
+    ///
+    /// # Arguments
+    /// * `token0` - First token address
+    /// * `token1` - Second token address
+    /// * `fee` - Fee tier
+    /// * `period` - Number of seconds in the past to start calculating the means from
+    ///
+    /// # Returns
+    /// * `arithmetic_mean_tick` - The arithmetic mean tick, rounded to negative infinity
+    /// * `harmonic_mean_liquidity` - The harmonic mean of in-range liquidity
+    pub fn consult(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: u32,
+        period: u32,
+    ) -> (i32, U128) {
+        if period == 0 {
+            self.env().revert(Error::ZeroPeriod);
+        }
+        let (tick_cumulatives, seconds_per_liquidity_cumulative_x128s) =
+            self.observe(token0, token1, fee, vec![period, 0]);
+
+        OracleLibrary::consult(
+            (tick_cumulatives[0], tick_cumulatives[1]),
+            (seconds_per_liquidity_cumulative_x128s[0], seconds_per_liquidity_cumulative_x128s[1]),
+            period,
+        )
+        .unwrap_or_revert(self)
+    }
 
-        Some(average_tick as i32)
+    /// Get the amount of `quote_token` received for `base_amount` of `base_token` at a given tick
+    ///
+    /// Typically used with the mean tick returned by `consult(base_token, quote_token, ..)`.
+    ///
+    /// # Arguments
+    /// * `tick` - Price of `base_token` in `quote_token`, in the caller's order: each tick up
+    ///   raises the quote by 0.01%
+    /// * `base_amount` - Amount of token to be converted
+    /// * `base_token` - Address of the token being quoted
+    /// * `quote_token` - Address of the token used as denomination of the quote
+    pub fn get_quote_at_tick(
+        &self,
+        tick: i32,
+        base_amount: U128,
+        base_token: Address,
+        quote_token: Address,
+    ) -> U256 {
+        if base_token == quote_token {
+            self.env().revert(Error::IdenticalTokens);
+        }
+        // OracleLibrary expects the tick in pool order
+        let (_, _, flipped) = Self::_sort_tokens(base_token, quote_token);
+        let tick = if flipped { -tick } else { tick };
+        OracleLibrary::get_quote_at_tick(tick, base_amount, base_token, quote_token).unwrap_or_revert(self)
     }
 
     /// Get observation at a specific index
@@ -1297,12 +2401,16 @@ impl UnifiedDex {
         fee: u32,
         index: u32,
     ) -> Option<Observation> {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
         let pool_id = Self::compute_pool_id(token0, token1, fee);
         self.observations.get(&(pool_id, index))
     }
 
     /// Increase the observation cardinality for more granular TWAP
     ///
+    /// The new observation slots are written here, so their storage cost is paid by the
+    /// caller rather than by later swaps. Requests at or below the pending cardinality are a no-op.
+    ///
     /// # Arguments
     /// * `token0` - First token address
     /// * `token1` - Second token address
@@ -1315,20 +2423,50 @@ impl UnifiedDex {
         fee: u32,
         cardinality_next: u32,
     ) {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
         let pool_key = (token0, token1, fee);
-        let mut pool_data = self.pools.get(&pool_key).expect("Pool does not exist");
+        self._lock(pool_key);
+        let mut pool_data = self.pools.get(&pool_key).unwrap_or_revert_with(self, Error::PoolDoesNotExist);
 
-        assert!(
-            cardinality_next > pool_data.slot0.observation_cardinality,
-            "Cardinality must increase"
-        );
-        assert!(
-            cardinality_next <= 65535,
-            "Cardinality too large"
-        );
+        let cardinality_next_old = pool_data.slot0.observation_cardinality_next;
+        if cardinality_next_old == 0 {
+            self.env().revert(Error::PoolNotInitialized);
+        }
+        if cardinality_next > 65535 {
+            self.env().revert(Error::CardinalityTooLarge);
+        }
 
-        pool_data.slot0.observation_cardinality_next = cardinality_next;
-        self.pools.set(&pool_key, pool_data);
+        // No-op if the requested size is already pending or in use
+        if cardinality_next > cardinality_next_old {
+            let pool_id = Self::compute_pool_id(token0, token1, fee);
+
+            // Store in each slot to prevent fresh writes when swaps later grow into it.
+            // These observations are not initialized, so the oracle skips them until written.
+            for index in cardinality_next_old..cardinality_next {
+                self.observations.set(
+                    &(pool_id, index),
+                    Observation {
+                        block_timestamp: 1,
+                        ..Default::default()
+                    },
+                );
+            }
+
+            pool_data.slot0.observation_cardinality_next = cardinality_next;
+            self.pools.set(&pool_key, pool_data);
+
+            self.env().emit_event(IncreaseObservationCardinalityNext {
+                token0,
+                token1,
+                fee,
+                pool_id,
+                sequence: self._next_event_sequence(pool_id),
+                observation_cardinality_next_old: cardinality_next_old,
+                observation_cardinality_next_new: cardinality_next,
+            });
+        }
+
+        self._unlock(pool_key);
     }
 
     /// Check if price manipulation is likely based on TWAP deviation
@@ -1348,6 +2486,7 @@ impl UnifiedDex {
         fee: u32,
         max_deviation_bps: u32,
     ) -> bool {
+        let (token0, token1, _) = Self::_sort_tokens(token0, token1);
         let pool_key = (token0, token1, fee);
         let pool_data = match self.pools.get(&pool_key) {
             Some(data) => data,
@@ -1379,4 +2518,1103 @@ pub struct QuoteResult {
     pub sqrt_price_x96_after: U256,
     pub tick_after: i32,
     pub fee_amount: U256,
-}
\ No newline at end of file
+}
+
+/// An initialized tick and the liquidity referencing it
+#[odra::odra_type]
+pub struct PopulatedTick {
+    pub tick: i32,
+    pub liquidity_net: I128,
+    pub liquidity_gross: U128,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use odra::casper_types::bytesrepr::{FromBytes, ToBytes};
+    use odra::host::{Deployer, HostEnv};
+    use odra_modules::{access::events::RoleGranted, erc20::Erc20HostRef};
+    use crate::access::ALL_ROLES;
+    use crate::test_fixtures::{add_liquidity, create_pool, deploy_dex, deploy_tokens, FEE, LIQUIDITY};
+
+    /// Deploys the DEX and two approved test tokens, and creates a 0.3% pool at price 1
+    fn setup() -> (HostEnv, UnifiedDexHostRef, Erc20HostRef, Erc20HostRef) {
+        let (env, mut dex) = deploy_dex();
+        let [mut token0, mut token1] = deploy_tokens(&env, ["TKA", "TKB"]);
+        token0.approve(&dex.address(), &U256::MAX);
+        token1.approve(&dex.address(), &U256::MAX);
+        create_pool(&mut dex, token0.address(), token1.address());
+
+        (env, dex, token0, token1)
+    }
+
+    #[test]
+    fn test_mint_updates_active_liquidity() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert_eq!(pool.liquidity, U128::from(LIQUIDITY));
+
+        // Out-of-range positions don't add active liquidity
+        dex.mint(
+            token0.address(), token1.address(), FEE, owner,
+            600, 1200, U128::from(1_000_000u128), U256::zero(), U256::zero(),
+        );
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert_eq!(pool.liquidity, U128::from(LIQUIDITY));
+    }
+
+    #[test]
+    fn test_snapshot_cumulatives_inside_tracks_in_range_time() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+        let liquidity = LIQUIDITY;
+
+        add_liquidity(&mut dex, owner, t0, t1);
+        env.advance_block_time(10_000);
+
+        // In range: the current accumulators minus both outside values
+        let (tick_cumulative, seconds_per_liquidity, seconds) =
+            dex.snapshot_cumulatives_inside(t0, t1, FEE, -600, 600);
+        assert_eq!(tick_cumulative, 0);
+        assert_eq!(seconds_per_liquidity, (U256::from(10u32) << 128) / U256::from(liquidity));
+        assert_eq!(seconds, 10);
+
+        // Push the price above the range; time spent there no longer counts
+        dex.swap(t0, t1, FEE, owner, false, I128::from_i128(1_000_000_000_000), TickMath::get_sqrt_ratio_at_tick(700).unwrap());
+        env.advance_block_time(10_000);
+        let (_, seconds_per_liquidity_above, seconds_above) =
+            dex.snapshot_cumulatives_inside(t0, t1, FEE, -600, 600);
+        assert_eq!(seconds_above, 10);
+        assert_eq!(seconds_per_liquidity_above, seconds_per_liquidity);
+
+        assert_eq!(
+            dex.try_snapshot_cumulatives_inside(t0, t1, FEE, -600, 660),
+            Err(Error::TickNotInitialized.into())
+        );
+    }
+
+    #[test]
+    fn test_increase_observation_cardinality_prewrites_slots() {
+        let (env, mut dex, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+        let pool_id = UnifiedDex::compute_pool_id(t0, t1, FEE);
+
+        dex.increase_observation_cardinality(t0, t1, FEE, 4);
+
+        let event: IncreaseObservationCardinalityNext = env.get_event(&dex, -1).unwrap();
+        assert_eq!((event.pool_id, event.sequence), (pool_id, 2));
+        assert_eq!((event.observation_cardinality_next_old, event.observation_cardinality_next_new), (1, 4));
+        for index in 1..4 {
+            let slot = dex.get_observation(t0, t1, FEE, index).unwrap();
+            assert_eq!(slot.block_timestamp, 1);
+            assert!(!slot.initialized);
+        }
+        let slot0 = dex.get_pool(t0, t1, FEE).unwrap().slot0;
+        assert_eq!((slot0.observation_cardinality, slot0.observation_cardinality_next), (1, 4));
+
+        // Already pending: accepted without a new event
+        dex.increase_observation_cardinality(t0, t1, FEE, 4);
+        dex.increase_observation_cardinality(t0, t1, FEE, 2);
+        assert_eq!(dex.get_event_sequence(t0, t1, FEE), 3);
+        assert_eq!(dex.get_pool(t0, t1, FEE).unwrap().slot0.observation_cardinality_next, 4);
+    }
+
+    #[test]
+    fn test_observe_searches_ring_buffer_and_extrapolates() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+
+        add_liquidity(&mut dex, owner, t0, t1);
+        dex.increase_observation_cardinality(t0, t1, FEE, 10);
+
+        // Three swaps ten seconds apart, each writing an observation for the tick before it
+        let mut ticks = Vec::new();
+        for (zero_for_one, amount) in [(true, 10_000_000_000i128), (false, 20_000_000_000), (true, 10_000_000_000)] {
+            env.advance_block_time(10_000);
+            dex.swap(t0, t1, FEE, owner, zero_for_one, I128::from_i128(amount), U256::zero());
+            ticks.push(dex.get_pool(t0, t1, FEE).unwrap().slot0.tick as i64);
+        }
+        env.advance_block_time(5_000);
+
+        let (tick_cumulatives, seconds_per_liquidity) = dex.observe(t0, t1, FEE, vec![35, 20, 10, 5, 0]);
+        assert_eq!(
+            tick_cumulatives,
+            vec![
+                0,                                                // initialization
+                ticks[0] * 5,                                     // interpolated between writes
+                ticks[0] * 10 + ticks[1] * 5,                     // interpolated between writes
+                ticks[0] * 10 + ticks[1] * 10,                    // exactly on the last write
+                ticks[0] * 10 + ticks[1] * 10 + ticks[2] * 5,     // extrapolated to now
+            ]
+        );
+        assert!(seconds_per_liquidity.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Windows older than the last two writes are now available to the TWAP
+        assert_eq!(dex.get_twap(t0, t1, FEE, 25, 5), Some(((ticks[0] * 10 + ticks[1] * 10) / 20) as i32));
+        // Empty and reversed windows have no average
+        assert_eq!(dex.get_twap(t0, t1, FEE, 5, 5), None);
+        assert_eq!(dex.get_twap(t0, t1, FEE, 5, 25), None);
+        assert_eq!(dex.try_observe(t0, t1, FEE, vec![36]), Err(Error::ObservationTooOld.into()));
+    }
+
+    #[test]
+    fn test_consult_and_quote_at_mean_tick() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+        let liquidity = LIQUIDITY;
+
+        add_liquidity(&mut dex, owner, t0, t1);
+        dex.increase_observation_cardinality(t0, t1, FEE, 10);
+
+        env.advance_block_time(10_000);
+        dex.swap(t0, t1, FEE, owner, true, I128::from_i128(10_000_000_000), U256::zero());
+        let tick = dex.get_pool(t0, t1, FEE).unwrap().slot0.tick;
+        assert!(tick < 0);
+        env.advance_block_time(10_000);
+
+        // Half the window at tick 0, half at the post-swap tick, rounded toward negative infinity
+        let (mean_tick, mean_liquidity) = dex.consult(t0, t1, FEE, 20);
+        assert_eq!(mean_tick, (tick as f64 / 2.0).floor() as i32);
+        let mean_liquidity = mean_liquidity.as_u128();
+        assert!(mean_liquidity <= liquidity && mean_liquidity >= liquidity - 1);
+
+        // Quotes take the tick in the order the tokens were consulted in
+        let amount = U128::from(1_000_000u128);
+        let quote = dex.get_quote_at_tick(mean_tick, amount, t0, t1);
+        assert!(quote < U256::from(1_000_000u128));
+        assert_eq!(quote, OracleLibrary::get_quote_at_tick(mean_tick, amount, t0, t1).unwrap());
+
+        let (reversed_tick, _) = dex.consult(t1, t0, FEE, 20);
+        assert_eq!(reversed_tick, (-tick as f64 / 2.0).floor() as i32);
+        let inverse = dex.get_quote_at_tick(reversed_tick, amount, t1, t0);
+        assert!(inverse > U256::from(1_000_000u128));
+        assert_eq!(inverse, OracleLibrary::get_quote_at_tick(-reversed_tick, amount, t1, t0).unwrap());
+
+        assert_eq!(dex.try_consult(t0, t1, FEE, 0), Err(Error::ZeroPeriod.into()));
+        assert_eq!(dex.try_consult(t0, t1, FEE, 21), Err(Error::ObservationTooOld.into()));
+    }
+
+    #[test]
+    fn test_tick_and_liquidity_distribution_views() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+
+        dex.mint(t0, t1, FEE, owner, -600, 600, U128::from(1_000_000u128), U256::zero(), U256::zero());
+        dex.mint(t0, t1, FEE, owner, -60, 600, U128::from(500_000u128), U256::zero(), U256::zero());
+        dex.mint(t0, t1, FEE, owner, 60_000, 60_060, U128::from(1_000u128), U256::zero(), U256::zero());
+
+        let upper = dex.get_tick(t0, t1, FEE, 600).unwrap();
+        assert_eq!(upper.liquidity_gross, U128::from(1_500_000u128));
+        assert_eq!(upper.liquidity_net.as_i128(), -1_500_000);
+        assert!(dex.get_tick(t0, t1, FEE, 60).is_none());
+
+        // -600 and -60 share the word below zero, 600 sits in word 0
+        let below: Vec<i32> = dex.get_populated_ticks_in_word(t0, t1, FEE, -1).iter().map(|t| t.tick).collect();
+        assert_eq!(below, vec![-600, -60]);
+        let above: Vec<i32> = dex.get_populated_ticks_in_word(t0, t1, FEE, 0).iter().map(|t| t.tick).collect();
+        assert_eq!(above, vec![600]);
+
+        // The range spans several words and is bounded on both ends
+        let distribution = dex.get_liquidity_distribution(t0, t1, FEE, -600, 60_000);
+        let ticks: Vec<(i32, i128)> = distribution.iter().map(|t| (t.tick, t.liquidity_net.as_i128())).collect();
+        assert_eq!(ticks, vec![(-600, 1_000_000), (-60, 500_000), (600, -1_500_000), (60_000, 1_000)]);
+
+        // Fully burned ticks are cleared and drop out of the views
+        dex.burn(t0, t1, FEE, -60, 600, U128::from(500_000u128));
+        assert!(dex.get_tick(t0, t1, FEE, -60).is_none());
+        assert_eq!(dex.get_liquidity_distribution(t0, t1, FEE, -600, 600).len(), 2);
+    }
+
+    #[test]
+    fn test_pool_and_position_ids_are_blake2b() {
+        let (env, dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+
+        let pool_id = dex.get_pool_id(token1.address(), token0.address(), FEE);
+        let encoded = (token0.address(), token1.address(), FEE).to_bytes().unwrap();
+        assert_eq!(pool_id, Digest::hash(encoded).value());
+        assert_eq!(pool_id, UnifiedDex::compute_pool_id(token0.address(), token1.address(), FEE));
+        assert_ne!(pool_id, dex.get_pool_id(token0.address(), token1.address(), 500));
+
+        let position_key = dex.get_position_key(owner, -600, 600);
+        assert_eq!(position_key, PositionKey::new(owner, -600, 600).compute_key());
+        assert_ne!(position_key, dex.get_position_key(owner, 600, -600));
+    }
+
+    #[test]
+    fn test_pool_enumeration_and_lookup() {
+        let (env, mut dex, token0, token1) = setup();
+        let pool_id = UnifiedDex::compute_pool_id(token0.address(), token1.address(), FEE);
+
+        // A pool on a newly enabled tier, created with the tokens reversed
+        dex.enable_fee_amount(100, 1);
+        dex.create_pool(token1.address(), token0.address(), 100);
+
+        assert_eq!(dex.all_pools_length(), 2);
+        assert_eq!(dex.pool_at(0).unwrap().fee, FEE);
+        assert_eq!(dex.pool_at(1).unwrap().fee, 100);
+        assert!(dex.pool_at(2).is_none());
+
+        let pool = dex.get_pool_by_id(pool_id).unwrap();
+        assert_eq!((pool.token0, pool.token1, pool.fee), (token0.address(), token1.address(), FEE));
+        assert!(dex.get_pool_by_id([0u8; 32]).is_none());
+
+        let fees: Vec<u32> = dex
+            .get_pools_for_pair(token1.address(), token0.address())
+            .iter()
+            .map(|pool| pool.fee)
+            .collect();
+        assert_eq!(fees, vec![FEE, 100]);
+        assert_eq!(dex.get_fee_amounts(), vec![500, 3000, 10000, 100]);
+        assert!(dex.get_pools_for_pair(token0.address(), env.get_account(5)).is_empty());
+    }
+
+    #[test]
+    fn test_max_liquidity_per_tick_spans_usable_ticks() {
+        let (_, dex, token0, token1) = setup();
+
+        // Same cap as Uniswap V3 for a tick spacing of 60
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert_eq!(
+            pool.max_liquidity_per_tick,
+            U128::from_dec_str("11505743598341114571880798222544994").unwrap()
+        );
+        assert_eq!(
+            Tick::tick_spacing_to_max_liquidity_per_tick(10),
+            U128::from_dec_str("1917569901783203986719870431555990").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_mint_rejects_liquidity_above_tick_cap() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let max_liquidity = dex.get_pool(token0.address(), token1.address(), FEE).unwrap().max_liquidity_per_tick;
+
+        // Near MAX_TICK the cap is cheap to reach: it only costs a little token0
+        dex.mint(
+            token0.address(), token1.address(), FEE, owner,
+            887160, 887220, max_liquidity, U256::zero(), U256::zero(),
+        );
+        let result = dex.try_mint(
+            token0.address(), token1.address(), FEE, owner,
+            887160, 887220, U128::one(), U256::zero(), U256::zero(),
+        );
+        assert_eq!(result, Err(Error::LiquidityExceedsMax.into()));
+    }
+
+    #[test]
+    fn test_swap_fees_accrue_to_in_range_positions() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let liquidity = U128::from(LIQUIDITY);
+
+        dex.mint(
+            token0.address(), token1.address(), FEE, owner,
+            -600, 600, liquidity, U256::zero(), U256::zero(),
+        );
+        dex.mint(
+            token0.address(), token1.address(), FEE, owner,
+            600, 1200, liquidity, U256::zero(), U256::zero(),
+        );
+
+        let amount_in = 1_000_000_000i128;
+        dex.swap(token0.address(), token1.address(), FEE, owner, true, I128::from_i128(amount_in), U256::zero());
+
+        let in_range = dex
+            .get_position_with_fees(token0.address(), token1.address(), FEE, owner, -600, 600)
+            .unwrap();
+        let out_of_range = dex
+            .get_position_with_fees(token0.address(), token1.address(), FEE, owner, 600, 1200)
+            .unwrap();
+
+        // The in-range position earns (almost) the whole 0.3% fee, paid in the input token
+        let max_fee = U128::from(amount_in as u128 * FEE as u128 / 1_000_000);
+        assert!(in_range.tokens_owed_0 > max_fee * 99 / 100);
+        assert!(in_range.tokens_owed_0 <= max_fee);
+        assert!(in_range.tokens_owed_1.is_zero());
+        assert!(out_of_range.tokens_owed_0.is_zero());
+        assert!(out_of_range.tokens_owed_1.is_zero());
+
+        // Collect pays out the accrued fees without burning any liquidity
+        let recipient = env.get_account(1);
+        let (collected0, collected1) = dex.collect(
+            token0.address(), token1.address(), FEE, recipient,
+            -600, 600, U128::MAX, U128::MAX,
+        );
+        assert_eq!(collected0, in_range.tokens_owed_0);
+        assert!(collected1.is_zero());
+        assert_eq!(token0.balance_of(&recipient), U256::from(collected0.as_u128()));
+    }
+
+    #[test]
+    fn test_fees_split_between_positions_by_liquidity() {
+        let (env, mut dex, token0, token1) = setup();
+        let alice = env.get_account(0);
+        let bob = env.get_account(1);
+
+        dex.mint(
+            token0.address(), token1.address(), FEE, alice,
+            -600, 600, U128::from(3_000_000_000_000u128), U256::zero(), U256::zero(),
+        );
+
+        // Bob joins with a third of Alice's liquidity
+        let mut bob_token0 = token0;
+        let mut bob_token1 = token1;
+        bob_token0.transfer(&bob, &U256::from(10u128.pow(20)));
+        bob_token1.transfer(&bob, &U256::from(10u128.pow(20)));
+        env.set_caller(bob);
+        bob_token0.approve(&dex.address(), &U256::MAX);
+        bob_token1.approve(&dex.address(), &U256::MAX);
+        dex.mint(
+            bob_token0.address(), bob_token1.address(), FEE, bob,
+            -600, 600, U128::from(LIQUIDITY), U256::zero(), U256::zero(),
+        );
+
+        env.set_caller(alice);
+        dex.swap(bob_token0.address(), bob_token1.address(), FEE, alice, false, I128::from_i128(1_000_000_000), U256::zero());
+
+        let alice_position = dex
+            .get_position_with_fees(bob_token0.address(), bob_token1.address(), FEE, alice, -600, 600)
+            .unwrap();
+        let bob_position = dex
+            .get_position_with_fees(bob_token0.address(), bob_token1.address(), FEE, bob, -600, 600)
+            .unwrap();
+
+        assert!(!bob_position.tokens_owed_1.is_zero());
+        let expected = bob_position.tokens_owed_1 * 3;
+        assert!(alice_position.tokens_owed_1 >= expected - 3 && alice_position.tokens_owed_1 <= expected + 3);
+    }
+
+    #[test]
+    fn test_burn_credits_principal_and_fees() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let liquidity = U128::from(LIQUIDITY);
+
+        let (minted0, _) = add_liquidity(&mut dex, owner, token0.address(), token1.address());
+        dex.swap(token0.address(), token1.address(), FEE, owner, true, I128::from_i128(1_000_000_000), U256::zero());
+
+        let (burned0, _) = dex.burn(token0.address(), token1.address(), FEE, -600, 600, liquidity);
+        let position = dex
+            .get_position_with_fees(token0.address(), token1.address(), FEE, owner, -600, 600)
+            .unwrap();
+
+        assert!(position.liquidity.is_zero());
+        assert!(burned0 > minted0);
+        assert!(U256::from(position.tokens_owed_0.as_u128()) > burned0);
+
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert!(pool.liquidity.is_zero());
+    }
+
+    #[test]
+    fn test_swap_emits_event() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let recipient = env.get_account(1);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+        let (amount0, amount1) =
+            dex.swap(token0.address(), token1.address(), FEE, recipient, true, I128::from_i128(1_000_000), U256::zero());
+
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        let event: Swap = env.get_event(&dex, -1).unwrap();
+        assert_eq!(
+            event,
+            Swap {
+                token0: token0.address(),
+                token1: token1.address(),
+                fee: FEE,
+                pool_id: UnifiedDex::compute_pool_id(token0.address(), token1.address(), FEE),
+                sequence: 3,
+                sender: owner,
+                recipient,
+                amount0: amount0.clone(),
+                amount1: amount1.clone(),
+                sqrt_price_x96: pool.slot0.sqrt_price_x96,
+                liquidity: pool.liquidity,
+                tick: pool.slot0.tick,
+            }
+        );
+        assert!(amount0.as_i128() > 0 && amount1.as_i128() < 0);
+    }
+
+    #[test]
+    fn test_swap_amounts_beyond_i64() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+
+        dex.mint(
+            token0.address(), token1.address(), FEE, owner,
+            -600, 600, U128::from(10u128.pow(22)), U256::zero(), U256::zero(),
+        );
+
+        // 100 tokens with 18 decimals is well past i64::MAX
+        let amount_in = 100 * 10i128.pow(18);
+        let balance1_before = token1.balance_of(&owner);
+        let (amount0, amount1) = dex.swap(
+            token0.address(), token1.address(), FEE, owner, true, I128::from_i128(amount_in), U256::zero(),
+        );
+
+        assert_eq!(amount0.as_i128(), amount_in);
+        assert!(-amount1.as_i128() > i64::MAX as i128);
+        assert_eq!(token1.balance_of(&owner) - balance1_before, U256::from(amount1.abs.as_u128()));
+    }
+
+    #[test]
+    fn test_swap_crosses_ticks_and_prices_each_range() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let wide = U128::from(LIQUIDITY);
+        let narrow = U128::from(10_000_000_000_000u128);
+
+        dex.mint(token0.address(), token1.address(), FEE, owner, -600, 600, wide, U256::zero(), U256::zero());
+        dex.mint(token0.address(), token1.address(), FEE, owner, -60, 60, narrow, U256::zero(), U256::zero());
+
+        // Enough to drain the narrow range and continue on the wide one alone
+        let amount_in = 40_000_000_000i128;
+        let (amount0, amount1) = dex.swap(
+            token0.address(), token1.address(), FEE, owner, true, I128::from_i128(amount_in), U256::zero(),
+        );
+
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert_eq!(amount0.as_i128(), amount_in);
+        assert!(amount1.as_i128() < 0);
+        assert!(pool.slot0.tick > -600 && pool.slot0.tick < -60);
+        assert_eq!(pool.liquidity, wide);
+        assert_eq!(pool.slot0.tick, TickMath::get_tick_at_sqrt_ratio(pool.slot0.sqrt_price_x96).unwrap());
+
+        // Swapping back across -60 re-activates the narrow range
+        dex.swap(
+            token0.address(), token1.address(), FEE, owner, false, I128::from_i128(amount_in), U256::zero(),
+        );
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert!(pool.slot0.tick >= -60 && pool.slot0.tick < 60);
+        assert_eq!(pool.liquidity, wide + narrow);
+    }
+
+    #[test]
+    fn test_swap_walks_empty_words_to_reach_liquidity() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let liquidity = U128::from(LIQUIDITY);
+
+        // The only liquidity sits three bitmap words below the current tick
+        dex.mint(token0.address(), token1.address(), FEE, owner, -40020, -39960, liquidity, U256::zero(), U256::zero());
+
+        let amount_in = 1_000_000i128;
+        let (amount0, amount1) = dex.swap(
+            token0.address(), token1.address(), FEE, owner, true, I128::from_i128(amount_in), U256::zero(),
+        );
+
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert_eq!(amount0.as_i128(), amount_in);
+        assert!(amount1.as_i128() < 0);
+        assert!(pool.slot0.tick >= -40020 && pool.slot0.tick < -39960);
+        assert_eq!(pool.liquidity, liquidity);
+    }
+
+    #[test]
+    fn test_exact_output_swap_delivers_requested_amount() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+
+        let balance0_before = token0.balance_of(&owner);
+        let balance1_before = token1.balance_of(&owner);
+        let (amount0, amount1) = dex.swap(
+            token0.address(), token1.address(), FEE, owner, false, I128::from_i128(-1_000_000), U256::zero(),
+        );
+
+        // Exactly the requested output, paid for with the input plus the fee on it
+        assert_eq!(amount0.as_i128(), -1_000_000);
+        assert!(amount1.as_i128() > 1_003_000);
+        assert_eq!(token0.balance_of(&owner) - balance0_before, U256::from(1_000_000u128));
+        assert_eq!(balance1_before - token1.balance_of(&owner), U256::from(amount1.abs.as_u128()));
+    }
+
+    #[test]
+    fn test_swap_stops_at_price_limit() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+
+        let limit = TickMath::get_sqrt_ratio_at_tick(-30).unwrap();
+        let amount_in = 1_000_000_000_000i128;
+        let (amount0, _) = dex.swap(
+            token0.address(), token1.address(), FEE, owner, true, I128::from_i128(amount_in), limit,
+        );
+
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        assert_eq!(pool.slot0.sqrt_price_x96, limit);
+        assert!(amount0.as_i128() > 0 && amount0.as_i128() < amount_in);
+    }
+
+    #[test]
+    fn test_unordered_tokens_follow_caller_order() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+        let liquidity = U128::from(LIQUIDITY);
+
+        // A range given in (token1, token0) order is stored negated and mirrored
+        let (amount1, amount0) = dex.mint(t1, t0, FEE, owner, -1200, 600, liquidity, U256::zero(), U256::zero());
+        assert!(dex.get_tick(t0, t1, FEE, -600).is_some() && dex.get_tick(t0, t1, FEE, 1200).is_some());
+        assert!(amount1 < amount0);
+        assert_eq!(
+            dex.get_position_with_fees(t1, t0, FEE, owner, -1200, 600).unwrap().liquidity,
+            dex.get_position_with_fees(t0, t1, FEE, owner, -600, 1200).unwrap().liquidity,
+        );
+
+        // Selling the first token of (token1, token0) sells token1, and the price limit is in that order
+        let limit = TickMath::get_sqrt_ratio_at_tick(-60).unwrap();
+        let (delta1, delta0) = dex.swap(t1, t0, FEE, owner, true, I128::from_i128(1_000_000_000_000), limit);
+        assert!(delta1.as_i128() > 0 && delta0.as_i128() < 0);
+        assert_eq!(dex.get_pool(t1, t0, FEE).unwrap().slot0.tick, 59);
+
+        // Fees owed on the position are reported in the caller's order as well
+        let position = dex.get_position_with_fees(t0, t1, FEE, owner, -600, 1200).unwrap();
+        let reversed = dex.get_position_with_fees(t1, t0, FEE, owner, -1200, 600).unwrap();
+        assert!(position.tokens_owed_0.is_zero() && !position.tokens_owed_1.is_zero());
+        assert_eq!((reversed.tokens_owed_0, reversed.tokens_owed_1), (position.tokens_owed_1, position.tokens_owed_0));
+
+        // Prices, ticks and cumulatives are inverted for the reverse order
+        assert_eq!(dex.get_price(t0, t1, FEE), Some(U256::one()));
+        assert_eq!(dex.get_price(t1, t0, FEE), Some(U256::zero()));
+        env.advance_block_time(10_000);
+        assert_eq!(dex.get_twap(t1, t0, FEE, 10, 0), Some(-59));
+        let (forward, _) = dex.observe(t0, t1, FEE, vec![0]);
+        let (reverse, _) = dex.observe(t1, t0, FEE, vec![0]);
+        assert_eq!(reverse[0], -forward[0]);
+
+        // Burned and collected amounts come back in the caller's order too
+        let (burned1, burned0) = dex.burn(t1, t0, FEE, -1200, 600, liquidity);
+        let balance0 = token0.balance_of(&owner);
+        let (collected1, collected0) = dex.collect(t1, t0, FEE, owner, -1200, 600, U128::MAX, U128::MAX);
+        assert!(collected1.as_u128() >= burned1.as_u128() && collected0.as_u128() >= burned0.as_u128());
+        assert_eq!(token0.balance_of(&owner) - balance0, U256::from(collected0.as_u128()));
+    }
+
+    #[test]
+    fn test_initialize_in_reverse_order_inverts_price() {
+        let (env, mut dex, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+
+        // Price 1.0001^6000 of token1 in token0
+        dex.create_pool(t1, t0, 500);
+        dex.initialize_pool(t1, t0, 500, TickMath::get_sqrt_ratio_at_tick(6000).unwrap());
+
+        let pool = dex.get_pool(t1, t0, 500).unwrap();
+        assert_eq!((pool.token0, pool.token1), (t0, t1));
+        assert!(pool.slot0.tick == -6000 || pool.slot0.tick == -6001);
+        assert!(dex.get_pool_id(t1, t0, 500) == dex.get_pool_id(t0, t1, 500));
+        assert_eq!(dex.get_price(t1, t0, 500), Some(U256::one()));
+        assert!(env.get_event::<Initialize, _>(&dex, -1).unwrap().tick < 0);
+    }
+
+    #[test]
+    fn test_create_and_initialize_pool_if_necessary() {
+        let (env, mut dex, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+        let sqrt_price = TickMath::get_sqrt_ratio_at_tick(6000).unwrap();
+
+        // Creates and prices a missing pool in one call, in the caller's token order
+        let pool_id = dex.create_and_initialize_pool_if_necessary(t1, t0, 500, sqrt_price);
+        assert_eq!(pool_id, dex.get_pool_id(t0, t1, 500));
+        let tick = dex.get_pool(t0, t1, 500).unwrap().slot0.tick;
+        assert!(tick == -6000 || tick == -6001);
+        assert_eq!(dex.get_pool_creator(t0, t1, 500), Some(env.get_account(0)));
+
+        // Repeating the call at the same price emits nothing
+        let sequence = dex.get_event_sequence(t0, t1, 500);
+        dex.create_and_initialize_pool_if_necessary(t1, t0, 500, sqrt_price);
+        dex.create_and_initialize_pool_if_necessary(t0, t1, FEE, U256::from(Q96));
+        assert_eq!(dex.get_event_sequence(t0, t1, 500), sequence);
+
+        // Initializes a pool that was created without a price
+        dex.create_pool(t0, t1, 10000);
+        dex.create_and_initialize_pool_if_necessary(t0, t1, 10000, U256::from(Q96));
+        assert_eq!(dex.get_pool(t0, t1, 10000).unwrap().slot0.sqrt_price_x96, U256::from(Q96));
+
+        // A pool listed at another price is never silently accepted
+        assert_eq!(
+            dex.try_create_and_initialize_pool_if_necessary(t0, t1, 10000, sqrt_price),
+            Err(Error::PoolPriceMismatch.into())
+        );
+        assert_eq!(dex.get_pool(t0, t1, 10000).unwrap().slot0.sqrt_price_x96, U256::from(Q96));
+    }
+
+    #[test]
+    fn test_restricted_initialization_limited_to_creator() {
+        let (env, mut dex, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+        let (creator, other) = (env.get_account(1), env.get_account(2));
+
+        // A pool created before the restriction keeps its creator
+        env.set_caller(creator);
+        dex.create_pool(t0, t1, 500);
+        env.set_caller(env.get_account(0));
+        dex.set_initialization_restricted(true);
+        assert!(dex.is_initialization_restricted());
+
+        env.set_caller(other);
+        assert_eq!(dex.try_initialize_pool(t0, t1, 500, U256::from(Q96)), Err(Error::NotPoolCreator.into()));
+        assert!(dex.try_set_initialization_restricted(false).is_err());
+
+        env.set_caller(creator);
+        dex.initialize_pool(t0, t1, 500, U256::from(Q96));
+        assert!(!dex.get_pool(t0, t1, 500).unwrap().slot0.sqrt_price_x96.is_zero());
+
+        // Other accounts cannot squat on an unpriced pool, but may create and price one in one call
+        env.set_caller(other);
+        assert_eq!(dex.try_create_pool(t0, t1, 10000), Err(Error::Unauthorized.into()));
+        dex.create_and_initialize_pool_if_necessary(t0, t1, 10000, U256::from(Q96));
+        assert_eq!(dex.get_pool_creator(t0, t1, 10000), Some(other));
+        assert_eq!(dex.get_pool(t0, t1, 10000).unwrap().slot0.sqrt_price_x96, U256::from(Q96));
+    }
+
+    #[test]
+    fn test_pool_events_carry_pool_identity_and_sequence() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+        let pool_id = UnifiedDex::compute_pool_id(token0.address(), token1.address(), FEE);
+
+        dex.mint(
+            token0.address(), token1.address(), FEE, owner,
+            -600, 600, U128::from(1_000_000u128), U256::zero(), U256::zero(),
+        );
+
+        let created: PoolCreated = env.get_event(&dex, -3).unwrap();
+        let initialized: Initialize = env.get_event(&dex, -2).unwrap();
+        let minted: Mint = env.get_event(&dex, -1).unwrap();
+        assert_eq!((created.pool_id, created.sequence), (pool_id, 0));
+        assert_eq!((initialized.pool_id, initialized.sequence), (pool_id, 1));
+        assert_eq!((minted.token0, minted.token1, minted.fee), (token0.address(), token1.address(), FEE));
+        assert_eq!((minted.pool_id, minted.sequence), (pool_id, 2));
+        assert_eq!(dex.get_event_sequence(token0.address(), token1.address(), FEE), 3);
+
+        // Sequences are tracked per pool
+        dex.create_pool(token0.address(), token1.address(), 500);
+        let created: PoolCreated = env.get_event(&dex, -1).unwrap();
+        assert_eq!(created.pool_id, UnifiedDex::compute_pool_id(token0.address(), token1.address(), 500));
+        assert_eq!(created.sequence, 0);
+        assert_eq!(dex.get_event_sequence(token0.address(), token1.address(), FEE), 3);
+    }
+
+    #[test]
+    fn test_protocol_fee_share_and_collect() {
+        let (env, mut dex, token0, token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+        dex.set_fee_protocol(token0.address(), token1.address(), FEE, 4, 5);
+        assert_eq!(dex.get_pool(token0.address(), token1.address(), FEE).unwrap().slot0.fee_protocol, 4 + (5 << 4));
+
+        dex.swap(token0.address(), token1.address(), FEE, owner, true, I128::from_i128(1_000_000_000), U256::zero());
+
+        // A quarter of the token0 fee goes to the protocol, the rest to LPs
+        let pool = dex.get_pool(token0.address(), token1.address(), FEE).unwrap();
+        let position = dex
+            .get_position_with_fees(token0.address(), token1.address(), FEE, owner, -600, 600)
+            .unwrap();
+        assert!(!pool.protocol_fees_token0.is_zero());
+        assert!(pool.protocol_fees_token1.is_zero());
+        let lp_share = pool.protocol_fees_token0 * 3;
+        assert!(position.tokens_owed_0 + 3 >= lp_share && position.tokens_owed_0 <= lp_share + 3);
+
+        let treasury = env.get_account(2);
+        let (collected0, collected1) = dex.collect_protocol(
+            token0.address(), token1.address(), FEE, treasury, U128::MAX, U128::MAX,
+        );
+        assert_eq!(collected0, pool.protocol_fees_token0);
+        assert!(collected1.is_zero());
+        assert_eq!(token0.balance_of(&treasury), U256::from(collected0.as_u128()));
+        assert!(dex.get_pool(token0.address(), token1.address(), FEE).unwrap().protocol_fees_token0.is_zero());
+    }
+
+    /// Borrows through `flash` and repays the loan, plus the fee when `repay_fee` is set
+    ///
+    /// With a re-entry set, the callback first performs one `PAUSE_*` action in the pool of the
+    /// given fee tier, paying for it with the borrowed tokens. With `share_payment` also set, a
+    /// re-entered mint is paid through its callback by the loan repayment alone.
+    #[odra::module]
+    struct FlashBorrower {
+        dex: Var<Address>,
+        token0: Var<Address>,
+        token1: Var<Address>,
+        fee: Var<u32>,
+        repay_fee: Var<bool>,
+        reenter: Var<Option<(u8, u32)>>,
+        share_payment: Var<bool>,
+    }
+
+    #[odra::module]
+    impl FlashBorrower {
+        pub fn init(&mut self, dex: Address, token0: Address, token1: Address, fee: u32, repay_fee: bool) {
+            self.dex.set(dex);
+            self.token0.set(token0);
+            self.token1.set(token1);
+            self.fee.set(fee);
+            self.repay_fee.set(repay_fee);
+        }
+
+        pub fn set_reenter(&mut self, action: u8, fee: u32) {
+            self.reenter.set(Some((action, fee)));
+        }
+
+        pub fn set_share_payment(&mut self, share_payment: bool) {
+            self.share_payment.set(share_payment);
+        }
+
+        pub fn borrow(&mut self, amount0: U256, amount1: U256) {
+            let data = (amount0, amount1).to_bytes().unwrap();
+            UnifiedDexContractRef::new(self.env(), self.dex.get().unwrap()).flash(
+                self.token0.get().unwrap(),
+                self.token1.get().unwrap(),
+                self.fee.get().unwrap(),
+                self.env().self_address(),
+                amount0,
+                amount1,
+                Bytes::from(data),
+            );
+        }
+
+        pub fn flash_callback(&mut self, fee0: U256, fee1: U256, data: Bytes) {
+            let dex = self.dex.get().unwrap();
+            let (amount0, amount1) = <(U256, U256)>::from_bytes(&data).unwrap().0;
+            let owed = if self.repay_fee.get().unwrap() {
+                (amount0 + fee0, amount1 + fee1)
+            } else {
+                (amount0, amount1)
+            };
+            if let Some((action, fee)) = self.reenter.get().flatten() {
+                self.reenter.set(None);
+                let (token0, token1) = (self.token0.get().unwrap(), self.token1.get().unwrap());
+                let mut dex_ref = UnifiedDexContractRef::new(self.env(), dex);
+                Erc20ContractRef::new(self.env(), token0).approve(&dex, &U256::MAX);
+                Erc20ContractRef::new(self.env(), token1).approve(&dex, &U256::MAX);
+                let this = self.env().self_address();
+                match action {
+                    PAUSE_MINT if self.share_payment.get_or_default() => {
+                        let data = Bytes::from(owed.to_bytes().unwrap());
+                        dex_ref.mint_with_callback(token0, token1, fee, this, -600, 600, U128::from(1_000u128), data);
+                        return;
+                    }
+                    PAUSE_MINT => {
+                        dex_ref.mint(token0, token1, fee, this, -600, 600, U128::from(1_000u128), U256::zero(), U256::zero());
+                    }
+                    PAUSE_SWAP => {
+                        dex_ref.swap(token0, token1, fee, this, true, I128::from_i128(1_000), U256::zero());
+                    }
+                    _ => {
+                        self.fee.set(fee);
+                        self.borrow(amount0, amount1);
+                    }
+                }
+            }
+            self.repay(owed);
+        }
+
+        pub fn mint_callback(&mut self, amount0_owed: U256, amount1_owed: U256, data: Bytes) {
+            // The loan repayment, which is enough to cover the mint on its own
+            let repayment = <(U256, U256)>::from_bytes(&data).unwrap().0;
+            assert!(repayment.0 >= amount0_owed && repayment.1 >= amount1_owed);
+            self.repay(repayment);
+        }
+
+        fn repay(&self, (owed0, owed1): (U256, U256)) {
+            let dex = self.dex.get().unwrap();
+            Erc20ContractRef::new(self.env(), self.token0.get().unwrap()).transfer(&dex, &owed0);
+            Erc20ContractRef::new(self.env(), self.token1.get().unwrap()).transfer(&dex, &owed1);
+        }
+    }
+
+    fn deploy_borrower(
+        env: &HostEnv,
+        dex: &UnifiedDexHostRef,
+        token0: &mut Erc20HostRef,
+        token1: &mut Erc20HostRef,
+        repay_fee: bool,
+    ) -> FlashBorrowerHostRef {
+        let borrower = FlashBorrower::deploy(
+            env,
+            FlashBorrowerInitArgs {
+                dex: dex.address(),
+                token0: token0.address(),
+                token1: token1.address(),
+                fee: FEE,
+                repay_fee,
+            },
+        );
+        // Fund the borrower so it can cover the fee
+        token0.transfer(&borrower.address(), &U256::from(1_000_000u128));
+        token1.transfer(&borrower.address(), &U256::from(1_000_000u128));
+        borrower
+    }
+
+    #[test]
+    fn test_flash_fee_accrues_to_lps() {
+        let (env, mut dex, mut token0, mut token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+        let mut borrower = deploy_borrower(&env, &dex, &mut token0, &mut token1, true);
+        let dex_balance0 = token0.balance_of(&dex.address());
+        let dex_balance1 = token1.balance_of(&dex.address());
+
+        let amount0 = U256::from(10_000_000u128);
+        let amount1 = U256::from(20_000_000u128);
+        borrower.borrow(amount0, amount1);
+
+        // 0.3% of each borrowed amount stays in the pool
+        let fee0 = U256::from(30_000u128);
+        let fee1 = U256::from(60_000u128);
+        assert_eq!(token0.balance_of(&dex.address()), dex_balance0 + fee0);
+        assert_eq!(token1.balance_of(&dex.address()), dex_balance1 + fee1);
+        assert!(env.emitted_event(
+            &dex,
+            Flash {
+                token0: token0.address(),
+                token1: token1.address(),
+                fee: FEE,
+                pool_id: UnifiedDex::compute_pool_id(token0.address(), token1.address(), FEE),
+                sequence: 3,
+                sender: borrower.address(),
+                recipient: borrower.address(),
+                amount0,
+                amount1,
+                paid0: fee0,
+                paid1: fee1,
+            }
+        ));
+
+        // The lock is released once the loan is repaid
+        assert!(dex.get_pool(token0.address(), token1.address(), FEE).unwrap().slot0.unlocked);
+
+        let position = dex
+            .get_position_with_fees(token0.address(), token1.address(), FEE, owner, -600, 600)
+            .unwrap();
+        assert!(U256::from(position.tokens_owed_0.as_u128()) + 1 >= fee0);
+        assert!(U256::from(position.tokens_owed_1.as_u128()) + 1 >= fee1);
+    }
+
+    #[test]
+    fn test_flash_reverts_without_fee() {
+        let (env, mut dex, mut token0, mut token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+        let mut borrower = deploy_borrower(&env, &dex, &mut token0, &mut token1, false);
+
+        assert!(borrower.try_borrow(U256::from(10_000_000u128), U256::zero()).is_err());
+
+        // Plain accounts cannot borrow
+        env.set_caller(owner);
+        assert!(dex
+            .try_flash(token0.address(), token1.address(), FEE, owner, U256::one(), U256::zero(), Bytes::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_flash_capped_at_pool_reserves() {
+        let (env, mut dex, mut token0, mut token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+
+        // The other pool's tokens sit in the same DEX balance but cannot be lent out by this one
+        let (amount0, amount1) = add_liquidity(&mut dex, owner, t0, t1);
+        dex.create_and_initialize_pool_if_necessary(t0, t1, 500, U256::from(Q96));
+        dex.mint(t0, t1, 500, owner, -600, 600, U128::from(LIQUIDITY), U256::zero(), U256::zero());
+        assert_eq!(dex.get_pool_reserves(t1, t0, FEE), (amount1, amount0));
+        assert!(token0.balance_of(&dex.address()) > amount0);
+
+        let mut borrower = deploy_borrower(&env, &dex, &mut token0, &mut token1, true);
+        assert_eq!(
+            borrower.try_borrow(amount0 + 1, U256::zero()),
+            Err(Error::InsufficientReserves.into())
+        );
+
+        // The whole reserve can be borrowed, with the fee added to it
+        token0.transfer(&borrower.address(), &amount0);
+        borrower.borrow(amount0, U256::zero());
+        let fee0 = FullMath::mul_div_rounding_up(amount0, U256::from(FEE), U256::from(1_000_000u32)).unwrap();
+        assert_eq!(dex.get_pool_reserves(t0, t1, FEE), (amount0 + fee0, amount1));
+    }
+
+    #[test]
+    fn test_pool_lock_rejects_reentry() {
+        let (env, mut dex, mut token0, mut token1) = setup();
+        let owner = env.get_account(0);
+
+        add_liquidity(&mut dex, owner, token0.address(), token1.address());
+        assert!(dex.get_pool(token0.address(), token1.address(), FEE).unwrap().slot0.unlocked);
+
+        let mut borrower = deploy_borrower(&env, &dex, &mut token0, &mut token1, true);
+        borrower.set_reenter(PAUSE_FLASH, FEE);
+        assert_eq!(borrower.try_borrow(U256::from(1_000u128), U256::zero()), Err(Error::PoolLocked.into()));
+    }
+
+    #[test]
+    fn test_reentry_into_other_pools_pays_separately() {
+        let (env, mut dex, mut token0, mut token1) = setup();
+        let owner = env.get_account(0);
+        let (t0, t1) = (token0.address(), token1.address());
+        let liquidity = U128::from(LIQUIDITY);
+
+        // A second pool of the same pair, holding its reserves in the same token balances
+        dex.create_and_initialize_pool_if_necessary(t0, t1, 500, U256::from(Q96));
+        dex.mint(t0, t1, FEE, owner, -600, 600, liquidity, U256::zero(), U256::zero());
+        dex.mint(t0, t1, 500, owner, -600, 600, liquidity, U256::zero(), U256::zero());
+
+        // Other pools stay open to a loan's callback as long as each operation is paid for
+        let mut borrower = deploy_borrower(&env, &dex, &mut token0, &mut token1, true);
+        for action in [PAUSE_MINT, PAUSE_SWAP, PAUSE_FLASH] {
+            borrower.set_reenter(action, 500);
+            borrower.borrow(U256::from(1_000_000u128), U256::from(1_000_000u128));
+        }
+
+        // One transfer cannot pay for both the nested mint and the loan
+        let mut borrower = deploy_borrower(&env, &dex, &mut token0, &mut token1, true);
+        borrower.set_reenter(PAUSE_MINT, 500);
+        borrower.set_share_payment(true);
+        assert_eq!(
+            borrower.try_borrow(U256::from(1_000_000u128), U256::from(1_000_000u128)),
+            Err(Error::FlashLoanNotRepaid.into())
+        );
+    }
+
+    #[test]
+    fn test_protocol_fee_admin_only() {
+        let (env, mut dex, token0, token1) = setup();
+
+        assert_eq!(
+            dex.try_set_fee_protocol(token0.address(), token1.address(), FEE, 3, 0),
+            Err(Error::InvalidFeeProtocol.into())
+        );
+
+        env.set_caller(env.get_account(1));
+        assert!(dex.try_set_fee_protocol(token0.address(), token1.address(), FEE, 4, 4).is_err());
+        env.set_caller(env.get_account(1));
+        assert!(dex
+            .try_collect_protocol(token0.address(), token1.address(), FEE, env.get_account(1), U128::MAX, U128::MAX)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pause_keeps_burn_and_collect_open() {
+        let (env, mut dex, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+        let owner = env.get_account(0);
+        let pauser = env.get_account(1);
+        let liquidity = U128::from(LIQUIDITY);
+        add_liquidity(&mut dex, owner, t0, t1);
+
+        dex.grant_role(PAUSER_ROLE, pauser);
+
+        // The pauser pauses swaps everywhere, and mints and flash loans in this pool only
+        env.set_caller(pauser);
+        dex.set_paused_actions(PAUSE_SWAP);
+        dex.set_pool_paused_actions(t1, t0, FEE, PAUSE_MINT | PAUSE_FLASH);
+        assert!(env.emitted_event(
+            &dex,
+            GlobalPauseSet { sender: pauser, paused_actions_old: 0, paused_actions_new: PAUSE_SWAP }
+        ));
+        let event: PoolPauseSet = env.get_event(&dex, -1).unwrap();
+        assert_eq!((event.token0, event.paused_actions_new), (t0, PAUSE_MINT | PAUSE_FLASH));
+        assert!(dex.is_paused(t0, t1, FEE, PAUSE_MINT) && dex.is_paused(t0, t1, 500, PAUSE_SWAP));
+        assert!(!dex.is_paused(t0, t1, 500, PAUSE_MINT | PAUSE_FLASH));
+
+        // LPs can always exit
+        env.set_caller(owner);
+        let (burned0, burned1) = dex.burn(t0, t1, FEE, -600, 600, liquidity);
+        let (collected0, collected1) = dex.collect(t0, t1, FEE, owner, -600, 600, U128::MAX, U128::MAX);
+        assert_eq!(U256::from(collected0.as_u128()), burned0);
+        assert_eq!(U256::from(collected1.as_u128()), burned1);
+
+        assert_eq!(
+            dex.try_mint(t0, t1, FEE, owner, -600, 600, liquidity, U256::zero(), U256::zero()),
+            Err(Error::Paused.into())
+        );
+        assert_eq!(
+            dex.try_swap(t0, t1, FEE, owner, true, I128::from_i128(1_000), U256::zero()),
+            Err(Error::Paused.into())
+        );
+        assert_eq!(
+            dex.try_flash(t0, t1, FEE, owner, U256::one(), U256::zero(), Bytes::new()),
+            Err(Error::Paused.into())
+        );
+
+        // Lifting the pool pause re-enables minting, while swaps stay paused globally
+        dex.set_pool_paused_actions(t0, t1, FEE, 0);
+        dex.mint(t0, t1, FEE, owner, -600, 600, liquidity, U256::zero(), U256::zero());
+        assert!(dex.is_paused(t0, t1, FEE, PAUSE_SWAP));
+
+        assert_eq!(dex.try_set_paused_actions(PAUSE_ALL + 1), Err(Error::InvalidPauseActions.into()));
+        env.set_caller(env.get_account(2));
+        assert_eq!(dex.try_set_paused_actions(0), Err(Error::Unauthorized.into()));
+    }
+
+    #[test]
+    fn test_roles_and_two_step_ownership() {
+        let (env, mut dex, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+        let (owner, collector, new_owner) = (env.get_account(0), env.get_account(1), env.get_account(2));
+
+        // The deployer starts as owner with every role
+        assert_eq!(dex.get_owner(), owner);
+        assert!(ALL_ROLES.iter().all(|role| dex.has_role(*role, owner)));
+
+        // A role only unlocks its own entry points
+        dex.grant_role(PROTOCOL_FEE_COLLECTOR_ROLE, collector);
+        assert!(env.emitted_event(&dex, RoleGranted { role: PROTOCOL_FEE_COLLECTOR_ROLE, address: collector, sender: owner }));
+        env.set_caller(collector);
+        dex.set_fee_protocol(t0, t1, FEE, 4, 4);
+        dex.collect_protocol(t0, t1, FEE, collector, U128::MAX, U128::MAX);
+        assert_eq!(dex.try_enable_fee_amount(100, 1), Err(Error::Unauthorized.into()));
+        assert_eq!(dex.try_set_paused_actions(PAUSE_ALL), Err(Error::Unauthorized.into()));
+        assert_eq!(dex.try_grant_role(PAUSER_ROLE, collector), Err(Error::Unauthorized.into()));
+
+        // Roles can be revoked by the owner or renounced by their holder
+        dex.renounce_role(PROTOCOL_FEE_COLLECTOR_ROLE);
+        assert!(!dex.has_role(PROTOCOL_FEE_COLLECTOR_ROLE, collector));
+        env.set_caller(owner);
+        dex.revoke_role(FEE_TIER_ADMIN_ROLE, owner);
+        assert_eq!(dex.try_enable_fee_amount(100, 1), Err(Error::Unauthorized.into()));
+
+        // Ownership only moves once the nominee accepts, taking the old owner's roles with it
+        dex.grant_role(PAUSER_ROLE, collector);
+        dex.transfer_ownership(new_owner);
+        assert_eq!((dex.get_owner(), dex.get_pending_owner()), (owner, Some(new_owner)));
+        assert!(dex.has_role(PAUSER_ROLE, owner) && !dex.has_role(PAUSER_ROLE, new_owner));
+        env.set_caller(new_owner);
+        dex.accept_ownership();
+        assert_eq!((dex.get_owner(), dex.get_pending_owner()), (new_owner, None));
+        assert!(ALL_ROLES.iter().all(|role| !dex.has_role(*role, owner)));
+        assert!(ALL_ROLES.iter().all(|role| dex.has_role(*role, new_owner) == (*role != FEE_TIER_ADMIN_ROLE)));
+        assert!(dex.has_role(PAUSER_ROLE, collector));
+        dex.set_paused_actions(PAUSE_ALL);
+        dex.set_paused_actions(0);
+
+        // Roles the old owner had given up are granted afresh
+        dex.grant_role(FEE_TIER_ADMIN_ROLE, new_owner);
+        dex.enable_fee_amount(100, 1);
+        assert_eq!(dex.get_fee_amounts(), vec![500, 3000, 10000, 100]);
+
+        env.set_caller(owner);
+        assert_eq!(dex.try_set_initialization_restricted(true), Err(Error::Unauthorized.into()));
+        assert_eq!(dex.try_set_paused_actions(PAUSE_ALL), Err(Error::Unauthorized.into()));
+        assert_eq!(dex.try_transfer_ownership(owner), Err(Error::Unauthorized.into()));
+        assert_eq!(dex.try_accept_ownership(), Err(Error::Unauthorized.into()));
+    }
+}
@@ -1,12 +1,21 @@
 use odra::{
-    casper_types::{U256, U128},
+    casper_types::{
+        bytesrepr::{Bytes, FromBytes, ToBytes},
+        U256, U128,
+    },
     prelude::*,
     ContractRef,
 };
-use crate::unified_dex::UnifiedDexContractRef;
+use odra_modules::erc20::Erc20ContractRef;
+use crate::{errors::Error, math::FullMath, types::position::Position, unified_dex::UnifiedDexContractRef};
 
 /// Simplified position manager for the unified DEX
-#[odra::module]
+///
+/// The manager owns every position it mints in the DEX, so it can burn and collect them on
+/// behalf of the token holders. Tokens with the same range share one DEX position; each token
+/// tracks its own liquidity, fee checkpoints and tokens owed, so a holder only ever collects
+/// their own share.
+#[odra::module(errors = Error)]
 pub struct UnifiedPositionManager {
     dex_address: Var<Address>,
     next_token_id: Var<u64>,
@@ -22,6 +31,11 @@ pub struct PositionInfo {
     pub tick_lower: i32,
     pub tick_upper: i32,
     pub liquidity: U128,
+    // Fee growth inside the range as of the last checkpoint, and amounts owed, in token0/token1 order
+    pub fee_growth_inside_0_last_x128: U256,
+    pub fee_growth_inside_1_last_x128: U256,
+    pub tokens_owed_0: U128,
+    pub tokens_owed_1: U128,
 }
 
 #[odra::odra_type]
@@ -47,21 +61,46 @@ impl UnifiedPositionManager {
         self.next_token_id.set(1);
     }
 
+    /// Create and initialize a pool in one call if necessary, see
+    /// `UnifiedDex::create_and_initialize_pool_if_necessary`
+    ///
+    /// The manager is recorded as the pool creator when it creates the pool.
+    pub fn create_and_initialize_pool_if_necessary(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+        sqrt_price_x96: U256,
+    ) -> [u8; 32] {
+        let dex_address = self.dex_address.get().unwrap();
+        UnifiedDexContractRef::new(self.env(), dex_address)
+            .create_and_initialize_pool_if_necessary(token_a, token_b, fee, sqrt_price_x96)
+    }
+
     /// Mint a new position NFT
     pub fn mint(&mut self, params: MintParams) -> u64 {
-        assert!(self.env().get_block_time() <= params.deadline, "Transaction too old");
+        if self.env().get_block_time() > params.deadline {
+            self.env().revert(Error::Expired);
+        }
 
         let dex_address = self.dex_address.get().unwrap();
         let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
 
         // Get pool to determine current price
         let pool_data = dex.get_pool(params.token0, params.token1, params.fee)
-            .expect("Pool does not exist");
+            .unwrap_or_revert_with(self, Error::PoolDoesNotExist);
 
         // Calculate tick prices
         let sqrt_price_lower = self.get_sqrt_ratio_at_tick(params.tick_lower);
         let sqrt_price_upper = self.get_sqrt_ratio_at_tick(params.tick_upper);
-        let sqrt_price_current = pool_data.slot0.sqrt_price_x96;
+        // Tokens may be unordered: ticks and amounts then follow the caller's order, so the pool
+        // price is inverted to match (see `UnifiedDex`)
+        let sqrt_price_current = if params.token0 < params.token1 {
+            pool_data.slot0.sqrt_price_x96
+        } else {
+            FullMath::mul_div(U256::one() << 96, U256::one() << 96, pool_data.slot0.sqrt_price_x96)
+                .unwrap_or_revert(self)
+        };
 
         // Calculate liquidity from desired amounts
         let liquidity = self.get_liquidity_for_amounts(
@@ -72,33 +111,43 @@ impl UnifiedPositionManager {
             params.amount1_desired,
         );
 
-        // Mint liquidity in DEX (slippage protection handled by DEX)
-        let (_amount0, _amount1) = dex.mint(
+        // Mint liquidity in DEX, paying the owed amounts from the caller in `mint_callback`
+        let payer = self.env().caller();
+        let data = (params.token0, params.token1, payer).to_bytes().unwrap();
+        let (amount0, amount1) = dex.mint_with_callback(
             params.token0,
             params.token1,
             params.fee,
-            params.recipient,
+            self.env().self_address(),
             params.tick_lower,
             params.tick_upper,
             liquidity,
-            params.amount0_min, // Slippage protection
-            params.amount1_min,
+            Bytes::from(data),
         );
 
-        // No need to verify here - DEX already checked slippage
-        // amounts are returned but not needed since we track liquidity in NFT position
+        // Slippage protection
+        if amount0 < params.amount0_min || amount1 < params.amount1_min {
+            self.env().revert(Error::SlippageExceeded);
+        }
 
         let token_id = self.next_token_id.get().unwrap();
         self.next_token_id.set(token_id + 1);
 
-        let position = PositionInfo {
+        let mut position = PositionInfo {
             token0: params.token0,
             token1: params.token1,
             fee: params.fee,
             tick_lower: params.tick_lower,
             tick_upper: params.tick_upper,
             liquidity,
+            fee_growth_inside_0_last_x128: U256::zero(),
+            fee_growth_inside_1_last_x128: U256::zero(),
+            tokens_owed_0: U128::zero(),
+            tokens_owed_1: U128::zero(),
         };
+        // Fees start accruing from the DEX position's checkpoint taken by this mint
+        (position.fee_growth_inside_0_last_x128, position.fee_growth_inside_1_last_x128) =
+            self.fee_growth_inside(&dex, &position);
 
         self.positions.set(&token_id, position);
         self.owners.set(&token_id, params.recipient);
@@ -106,10 +155,75 @@ impl UnifiedPositionManager {
         token_id
     }
 
+    /// DEX mint callback: pays the owed amounts from the payer encoded in `data`.
+    /// Only the DEX may call this.
+    pub fn mint_callback(&mut self, amount0_owed: U256, amount1_owed: U256, data: Bytes) {
+        let dex_address = self.dex_address.get().unwrap();
+        if self.env().caller() != dex_address {
+            self.env().revert(Error::Unauthorized);
+        }
+
+        let ((token0, token1, payer), _) =
+            <(Address, Address, Address)>::from_bytes(&data).unwrap_or_revert_with(self, Error::InvalidCallbackData);
+        if !amount0_owed.is_zero() {
+            Erc20ContractRef::new(self.env(), token0).transfer_from(&payer, &dex_address, &amount0_owed);
+        }
+        if !amount1_owed.is_zero() {
+            Erc20ContractRef::new(self.env(), token1).transfer_from(&payer, &dex_address, &amount1_owed);
+        }
+    }
+
+    /// Helper: Fee growth inside the manager's DEX position for a token's range as of the
+    /// position's last checkpoint, in the token's `token0`/`token1` order
+    fn fee_growth_inside(&self, dex: &UnifiedDexContractRef, position: &PositionInfo) -> (U256, U256) {
+        let dex_position = dex
+            .get_position_with_fees(
+                position.token0,
+                position.token1,
+                position.fee,
+                self.env().self_address(),
+                position.tick_lower,
+                position.tick_upper,
+            )
+            .unwrap_or_revert_with(self, Error::PositionNotFound);
+        (dex_position.fee_growth_inside_0_last_x128, dex_position.fee_growth_inside_1_last_x128)
+    }
+
+    /// Helper: Credit the fees a token earned since its last checkpoint and apply `liquidity_delta`,
+    /// once the shared DEX position has just been checkpointed
+    fn update_position(&self, dex: &UnifiedDexContractRef, position: &mut PositionInfo, liquidity_delta: i128) {
+        let (fee_growth_inside_0_x128, fee_growth_inside_1_x128) = self.fee_growth_inside(dex, position);
+        let mut accounting = Position {
+            liquidity: position.liquidity,
+            fee_growth_inside_0_last_x128: position.fee_growth_inside_0_last_x128,
+            fee_growth_inside_1_last_x128: position.fee_growth_inside_1_last_x128,
+            tokens_owed_0: position.tokens_owed_0,
+            tokens_owed_1: position.tokens_owed_1,
+        };
+        accounting
+            .update(liquidity_delta, fee_growth_inside_0_x128, fee_growth_inside_1_x128)
+            .unwrap_or_revert(self);
+
+        position.liquidity = accounting.liquidity;
+        position.fee_growth_inside_0_last_x128 = accounting.fee_growth_inside_0_last_x128;
+        position.fee_growth_inside_1_last_x128 = accounting.fee_growth_inside_1_last_x128;
+        position.tokens_owed_0 = accounting.tokens_owed_0;
+        position.tokens_owed_1 = accounting.tokens_owed_1;
+    }
+
+    /// Helper: Add `amount` to a token's 128-bit balance owed, reverting if the sum does not fit
+    fn add_owed(&self, owed: U128, amount: U256) -> U128 {
+        if amount > U256::from(u128::MAX) {
+            self.env().revert(Error::FeesOverflow);
+        }
+        owed.checked_add(U128::from(amount.as_u128()))
+            .unwrap_or_revert_with(self, Error::FeesOverflow)
+    }
+
     /// Helper: Get sqrt ratio at tick (copied from TickMath for convenience)
     fn get_sqrt_ratio_at_tick(&self, tick: i32) -> U256 {
         use crate::math::TickMath;
-        TickMath::get_sqrt_ratio_at_tick(tick)
+        TickMath::get_sqrt_ratio_at_tick(tick).unwrap_or_revert(self)
     }
 
     /// Helper: Calculate liquidity for given amounts
@@ -129,6 +243,7 @@ impl UnifiedPositionManager {
             amount0,
             amount1,
         )
+        .unwrap_or_revert(self)
     }
 
     /// Decrease liquidity from a position
@@ -140,13 +255,17 @@ impl UnifiedPositionManager {
         amount1_min: U256,
         deadline: u64,
     ) -> (U256, U256) {
-        assert!(self.env().get_block_time() <= deadline, "Transaction too old");
+        if self.env().get_block_time() > deadline {
+            self.env().revert(Error::Expired);
+        }
         
         let caller = self.env().caller();
-        let owner = self.owners.get(&token_id).expect("Invalid token ID");
-        assert!(caller == owner, "Not authorized");
+        let owner = self.owners.get(&token_id).unwrap_or_revert_with(self, Error::PositionNotFound);
+        if caller != owner {
+            self.env().revert(Error::Unauthorized);
+        }
 
-        let position = self.positions.get(&token_id).expect("Position not found");
+        let mut position = self.positions.get(&token_id).unwrap_or_revert_with(self, Error::PositionNotFound);
         
         let dex_address = self.dex_address.get().unwrap();
         let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
@@ -160,13 +279,16 @@ impl UnifiedPositionManager {
             liquidity,
         );
 
-        assert!(amount0 >= amount0_min, "Amount0 too low");
-        assert!(amount1 >= amount1_min, "Amount1 too low");
+        if amount0 < amount0_min || amount1 < amount1_min {
+            self.env().revert(Error::SlippageExceeded);
+        }
 
-        // Update position
-        let mut updated_position = position;
-        updated_position.liquidity = updated_position.liquidity - liquidity;
-        self.positions.set(&token_id, updated_position);
+        // Credit fees earned so far, then the burned principal, to this token
+        let liquidity_delta = i128::try_from(liquidity.as_u128()).ok().unwrap_or_revert_with(self, Error::LiquidityOverflow);
+        self.update_position(&dex, &mut position, -liquidity_delta);
+        position.tokens_owed_0 = self.add_owed(position.tokens_owed_0, amount0);
+        position.tokens_owed_1 = self.add_owed(position.tokens_owed_1, amount1);
+        self.positions.set(&token_id, position);
 
         (amount0, amount1)
     }
@@ -180,24 +302,45 @@ impl UnifiedPositionManager {
         amount1_max: U128,
     ) -> (U128, U128) {
         let caller = self.env().caller();
-        let owner = self.owners.get(&token_id).expect("Invalid token ID");
-        assert!(caller == owner, "Not authorized");
+        let owner = self.owners.get(&token_id).unwrap_or_revert_with(self, Error::PositionNotFound);
+        if caller != owner {
+            self.env().revert(Error::Unauthorized);
+        }
 
-        let position = self.positions.get(&token_id).expect("Position not found");
+        let mut position = self.positions.get(&token_id).unwrap_or_revert_with(self, Error::PositionNotFound);
         
         let dex_address = self.dex_address.get().unwrap();
         let mut dex = UnifiedDexContractRef::new(self.env(), dex_address);
 
-        dex.collect(
+        // Checkpoint the shared DEX position with an empty burn, then credit this token's fees
+        if !position.liquidity.is_zero() {
+            dex.burn(
+                position.token0,
+                position.token1,
+                position.fee,
+                position.tick_lower,
+                position.tick_upper,
+                U128::zero(),
+            );
+            self.update_position(&dex, &mut position, 0);
+        }
+
+        let (amount0, amount1) = dex.collect(
             position.token0,
             position.token1,
             position.fee,
             recipient,
             position.tick_lower,
             position.tick_upper,
-            amount0_max,
-            amount1_max,
-        )
+            amount0_max.min(position.tokens_owed_0),
+            amount1_max.min(position.tokens_owed_1),
+        );
+
+        position.tokens_owed_0 -= amount0;
+        position.tokens_owed_1 -= amount1;
+        self.positions.set(&token_id, position);
+
+        (amount0, amount1)
     }
 
     // Getters
@@ -208,4 +351,169 @@ impl UnifiedPositionManager {
     pub fn owner_of(&self, token_id: u64) -> Option<Address> {
         self.owners.get(&token_id)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::Q96,
+        test_fixtures::{create_pool, deploy_dex, deploy_tokens, FEE},
+        types::tick::I128,
+        unified_dex::UnifiedDexHostRef,
+    };
+    use odra::host::{Deployer, HostEnv};
+    use odra_modules::erc20::Erc20HostRef;
+
+    /// Deploys the DEX with a 0.3% pool at price 1 and a manager approved to spend both tokens
+    fn setup() -> (HostEnv, UnifiedDexHostRef, UnifiedPositionManagerHostRef, Erc20HostRef, Erc20HostRef) {
+        let (env, mut dex) = deploy_dex();
+        let manager = UnifiedPositionManager::deploy(
+            &env,
+            UnifiedPositionManagerInitArgs { dex_address: dex.address() },
+        );
+        let [mut token0, mut token1] = deploy_tokens(&env, ["TKA", "TKB"]);
+        // Only the manager is approved; it pays the DEX from `mint_callback`
+        token0.approve(&manager.address(), &U256::MAX);
+        token1.approve(&manager.address(), &U256::MAX);
+        create_pool(&mut dex, token0.address(), token1.address());
+
+        (env, dex, manager, token0, token1)
+    }
+
+    /// Mint `amount` of each token at most on `tick_lower..tick_upper` of the 0.3% pool, with no
+    /// minimums or deadline
+    fn mint_params(
+        token0: Address,
+        token1: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u128,
+        recipient: Address,
+    ) -> MintParams {
+        MintParams {
+            token0,
+            token1,
+            fee: FEE,
+            tick_lower,
+            tick_upper,
+            amount0_desired: U256::from(amount),
+            amount1_desired: U256::from(amount),
+            amount0_min: U256::zero(),
+            amount1_min: U256::zero(),
+            recipient,
+            deadline: u64::MAX,
+        }
+    }
+
+    #[test]
+    fn test_mint_paid_through_callback() {
+        let (env, dex, mut manager, token0, token1) = setup();
+        let lp = env.get_account(0);
+
+        let token_id = manager.mint(mint_params(token0.address(), token1.address(), -600, 600, 1_000_000, lp));
+
+        let position = manager.get_position(token_id).unwrap();
+        assert!(!position.liquidity.is_zero());
+        assert!(!token0.balance_of(&dex.address()).is_zero());
+        assert!(!token1.balance_of(&dex.address()).is_zero());
+        assert!(token0.balance_of(&manager.address()).is_zero());
+        assert_eq!(
+            dex.get_pool(token0.address(), token1.address(), 3000).unwrap().liquidity,
+            position.liquidity
+        );
+    }
+
+    #[test]
+    fn test_create_and_initialize_pool_through_manager() {
+        let (_env, dex, mut manager, token0, token1) = setup();
+        let (t0, t1) = (token0.address(), token1.address());
+
+        let pool_id = manager.create_and_initialize_pool_if_necessary(t0, t1, 500, U256::from(Q96));
+        assert_eq!(pool_id, dex.get_pool_id(t0, t1, 500));
+        assert_eq!(dex.get_pool(t0, t1, 500).unwrap().slot0.tick, 0);
+        assert_eq!(dex.get_pool_creator(t0, t1, 500), Some(manager.address()));
+
+        // The existing 0.3% pool is already at this price
+        manager.create_and_initialize_pool_if_necessary(t1, t0, 3000, U256::from(Q96));
+    }
+
+    #[test]
+    fn test_mint_accepts_unordered_tokens() {
+        let (env, dex, mut manager, token0, token1) = setup();
+        let lp = env.get_account(0);
+
+        // The range [0, 600) in (token1, token0) order sits entirely in token1
+        let token_id = manager.mint(mint_params(token1.address(), token0.address(), 0, 600, 1_000_000, lp));
+
+        let position = manager.get_position(token_id).unwrap();
+        assert!(!position.liquidity.is_zero());
+        assert!(token0.balance_of(&dex.address()).is_zero());
+        assert!(!token1.balance_of(&dex.address()).is_zero());
+        assert!(dex.get_tick(token0.address(), token1.address(), 3000, -600).is_some());
+        assert_eq!(
+            dex.get_position_with_fees(token1.address(), token0.address(), 3000, manager.address(), 0, 600)
+                .unwrap()
+                .liquidity,
+            position.liquidity
+        );
+    }
+
+    #[test]
+    fn test_mint_decrease_collect_round_trip() {
+        let (env, mut dex, mut manager, mut token0, mut token1) = setup();
+        let (payer, holder, other) = (env.get_account(0), env.get_account(1), env.get_account(2));
+        let params = mint_params(token0.address(), token1.address(), -600, 600, 1_000_000_000, holder);
+
+        // Two tokens on the same range share the manager's DEX position
+        let token_id = manager.mint(params.clone());
+        let other_id = manager.mint(MintParams { recipient: other, ..params });
+        let liquidity = manager.get_position(token_id).unwrap().liquidity;
+        assert!(dex.get_position_with_fees(token0.address(), token1.address(), 3000, holder, -600, 600).is_none());
+
+        // Earn fees in token0
+        token0.approve(&dex.address(), &U256::MAX);
+        token1.approve(&dex.address(), &U256::MAX);
+        dex.swap(token0.address(), token1.address(), 3000, payer, true, I128::from_i128(10_000_000), U256::zero());
+
+        env.set_caller(holder);
+        let (burned0, burned1) = manager.decrease_liquidity(token_id, liquidity, U256::zero(), U256::zero(), u64::MAX);
+        let position = manager.get_position(token_id).unwrap();
+        assert!(position.liquidity.is_zero());
+        assert!(U256::from(position.tokens_owed_0.as_u128()) > burned0);
+        assert_eq!(U256::from(position.tokens_owed_1.as_u128()), burned1);
+
+        // The holder collects their principal and half the fees, and nothing of the other token's share
+        let (collected0, collected1) = manager.collect(token_id, holder, U128::MAX, U128::MAX);
+        assert_eq!((collected0, collected1), (position.tokens_owed_0, position.tokens_owed_1));
+        assert_eq!(token0.balance_of(&holder), U256::from(collected0.as_u128()));
+        assert_eq!(token1.balance_of(&holder), U256::from(collected1.as_u128()));
+        assert_eq!(manager.collect(token_id, holder, U128::MAX, U128::MAX), (U128::zero(), U128::zero()));
+
+        env.set_caller(other);
+        let (fees0, fees1) = manager.collect(other_id, other, U128::MAX, U128::MAX);
+        assert!(fees1.is_zero());
+        let holder_fees0 = collected0.as_u128() - burned0.as_u128();
+        assert!(fees0.as_u128() + 1 >= holder_fees0 && fees0.as_u128() <= holder_fees0 + 1);
+        assert!(manager.get_position(other_id).unwrap().tokens_owed_0.is_zero());
+
+        // Only the holder may manage their token
+        assert_eq!(manager.try_collect(token_id, other, U128::MAX, U128::MAX), Err(Error::Unauthorized.into()));
+    }
+
+    #[test]
+    fn test_rejects_expired_and_unknown_positions() {
+        let (env, _dex, mut manager, token0, token1) = setup();
+        env.advance_block_time(1_000);
+
+        let params = MintParams {
+            deadline: 0,
+            ..mint_params(token0.address(), token1.address(), -600, 600, 1_000_000, env.get_account(0))
+        };
+        assert_eq!(manager.try_mint(params), Err(Error::Expired.into()));
+        assert_eq!(
+            manager.try_decrease_liquidity(1, U128::one(), U256::zero(), U256::zero(), u64::MAX),
+            Err(Error::PositionNotFound.into())
+        );
+    }
+}